@@ -0,0 +1,109 @@
+//! Measures the cycles taken by the mapping of `parse_encoder`, comparing the fixed-point mapping
+//! with the float one it replaced.
+//!
+//! Run it with `cargo run --release --example encoder_bench --features bench`, then read the
+//! `RESULTS` static with a debugger (e.g. `probe-rs` or `gdb`) once the onboard LED turns on.
+
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+
+use core::hint::black_box;
+use core::ptr::addr_of_mut;
+
+use pico_sdvx_ac::*;
+
+use rp_pico as bsp;
+
+use bsp::entry;
+use bsp::hal;
+
+use hal::pac;
+
+use cortex_m::peripheral::{syst::SystClkSource, SYST};
+use embedded_hal::digital::OutputPin;
+
+
+/// The counts of a full revolution at the default resolution.
+const PULSE: i32 = ENC_DEFAULT_PPR as i32 * ENC_COUNTS_PER_PULSE;
+/// The SysTick counter is 24 bits wide.
+const SYST_MAX: u32 = 0x00FF_FFFF;
+
+
+/// The average cycles per call of the float and fixed-point mappings, in that order.
+#[no_mangle]
+static mut RESULTS: [u32; 2] = [0; 2];
+
+
+/// The mapping `parse_encoder` used to do with floats.
+#[inline(never)]
+fn float_position(count: i32, pulse: i32) -> u8 {
+	((count as f64 / pulse as f64) * (u8::MAX as f64 + 1.0)) as u8
+}
+
+#[inline(never)]
+fn fixed_position(count: i32, pulse: i32) -> u8 {
+	encoder_position(count * ENC_SENSITIVITY_SCALE, pulse)
+}
+
+/// Returns the average cycles taken by the mapping over every count of an encoder.
+fn measure(syst: &mut SYST, mapping: fn(i32, i32) -> u8) -> u32 {
+	syst.clear_current();
+
+	let start = SYST::get_current();
+
+	for count in 0..PULSE {
+		black_box(mapping(black_box(count), black_box(PULSE)));
+	}
+
+	let end = SYST::get_current();
+
+	// The counter counts down.
+	(start.wrapping_sub(end) & SYST_MAX) / PULSE as u32
+}
+
+
+#[entry]
+fn main() -> ! {
+	let mut pac = pac::Peripherals::take().unwrap();
+	let mut core = pac::CorePeripherals::take().unwrap();
+	let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+	let _clocks = hal::clocks::init_clocks_and_plls(
+		bsp::XOSC_CRYSTAL_FREQ,
+		pac.XOSC,
+		pac.CLOCKS,
+		pac.PLL_SYS,
+		pac.PLL_USB,
+		&mut pac.RESETS,
+		&mut watchdog,
+	)
+		.ok()
+		.unwrap();
+
+	let sio = hal::Sio::new(pac.SIO);
+	let pins = bsp::Pins::new(
+		pac.IO_BANK0,
+		pac.PADS_BANK0,
+		sio.gpio_bank0,
+		&mut pac.RESETS,
+	);
+
+	// SysTick counts the core's cycles.
+	core.SYST.set_clock_source(SystClkSource::Core);
+	core.SYST.set_reload(SYST_MAX);
+	core.SYST.enable_counter();
+
+	let results = unsafe { &mut *addr_of_mut!(RESULTS) };
+
+	results[0] = measure(&mut core.SYST, float_position);
+	results[1] = measure(&mut core.SYST, fixed_position);
+
+	// Signals the results are ready.
+	pins.led.into_push_pull_output().set_high().unwrap();
+
+	loop {
+		cortex_m::asm::wfi();
+	}
+}
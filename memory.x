@@ -1,6 +1,8 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 4K
+    /* Last sector of the flash, reserved for the controller options (see src/storage.rs). */
+    STORAGE : ORIGIN = 0x101FF000, LENGTH = 4K
     RAM   : ORIGIN = 0x20000000, LENGTH = 256K
 }
 
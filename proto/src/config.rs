@@ -0,0 +1,125 @@
+/// The size (in bytes) of the configuration feature report.
+pub const CONFIG_REPORT_SIZE: usize = 8;
+/// The version of the configuration protocol, bumped whenever it changes in an incompatible way.
+pub const CONFIG_PROTOCOL_VERSION: u32 = 1;
+
+
+// Requests and responses share the same layout (little endian):
+// [COMMAND] -> [STATUS] -> [FIELD] -> [INDEX] -> [VALUE (4 bytes)]
+// The host writes a request with SET_REPORT and reads its response back with GET_REPORT.
+// The status of a request is ignored, the response echoes the request with its status filled in.
+
+
+/// The commands understood by the configuration protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigCommand {
+	/// Reads the protocol version, see [`CONFIG_PROTOCOL_VERSION`].
+	Version = 0x01,
+	/// Reads the value at the given field and index.
+	Get = 0x02,
+	/// Writes the value to the given field and index. The change is applied immediately.
+	Set = 0x03,
+	/// Writes the current options to flash, so they are restored on the next boot.
+	Commit = 0x04,
+	/// Restores the default options. They aren't written to flash until committed.
+	Reset = 0x05,
+	/// Reads the diagnostic counter at the given field and index, see [`DiagnosticField`].
+	Diagnostic = 0x06,
+}
+
+impl ConfigCommand {
+	/// Every command, ordered by their identifier.
+	pub const ALL: [ConfigCommand; 6] = [
+		ConfigCommand::Version,
+		ConfigCommand::Get,
+		ConfigCommand::Set,
+		ConfigCommand::Commit,
+		ConfigCommand::Reset,
+		ConfigCommand::Diagnostic,
+	];
+
+	/// Returns the command matching the given identifier, if any.
+	pub fn from_id(id: u8) -> Option<Self> {
+		Self::ALL.into_iter().find(|command| *command as u8 == id)
+	}
+}
+
+
+/// The outcome of a request of the configuration protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigStatus {
+	/// The request was handled successfully.
+	Ok = 0x00,
+	/// The request hasn't been handled yet, the response should be read again.
+	Pending = 0x01,
+	/// The command isn't part of the protocol.
+	UnknownCommand = 0x02,
+	/// The field isn't an option of the controller.
+	UnknownField = 0x03,
+	/// The index is out of range for the field.
+	InvalidIndex = 0x04,
+	/// The value can't be held by the field.
+	InvalidValue = 0x05,
+}
+
+impl ConfigStatus {
+	/// Every status, ordered by their identifier.
+	pub const ALL: [ConfigStatus; 6] = [
+		ConfigStatus::Ok,
+		ConfigStatus::Pending,
+		ConfigStatus::UnknownCommand,
+		ConfigStatus::UnknownField,
+		ConfigStatus::InvalidIndex,
+		ConfigStatus::InvalidValue,
+	];
+
+	/// Returns the status matching the given identifier, if any.
+	pub fn from_id(id: u8) -> Option<Self> {
+		Self::ALL.into_iter().find(|status| *status as u8 == id)
+	}
+}
+
+
+/// A request or response of the configuration protocol.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConfigMessage {
+	pub command: u8,
+	pub status: u8,
+	pub field: u8,
+	pub index: u8,
+	pub value: u32,
+}
+
+impl ConfigMessage {
+	/// Generates a new request for the given command.
+	pub fn request(command: ConfigCommand, field: u8, index: u8, value: u32) -> Self {
+		Self { command: command as u8, status: ConfigStatus::Ok as u8, field, index, value }
+	}
+
+	/// Generates the response to this request with the given status.
+	pub fn respond(&self, status: ConfigStatus, value: u32) -> Self {
+		Self { status: status as u8, value, ..*self }
+	}
+
+	/// Parses a message from the contents of a feature report.
+	///
+	/// Returns `None` if the report is shorter than [`CONFIG_REPORT_SIZE`].
+	pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+		if bytes.len() < CONFIG_REPORT_SIZE { return None; }
+
+		Some(Self {
+			command: bytes[0],
+			status: bytes[1],
+			field: bytes[2],
+			index: bytes[3],
+			value: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+		})
+	}
+
+	/// Serializes the message into the contents of a feature report.
+	pub fn to_bytes(&self) -> [u8; CONFIG_REPORT_SIZE] {
+		let value = self.value.to_le_bytes();
+
+		[self.command, self.status, self.field, self.index, value[0], value[1], value[2], value[3]]
+	}
+}
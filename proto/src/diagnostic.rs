@@ -0,0 +1,39 @@
+use crate::*;
+
+
+/// Identifies each diagnostic counter of the controller once serialized.
+/// Counters holding several values (one per encoder, etc.) are addressed by index.
+/// They are read-only and reset on every boot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiagnosticField {
+	/// One counter per encoder, counting the updates during which its state machine stalled on
+	/// a full RX FIFO, dropping pulses. A lower bound of the stalls, as several stalls between
+	/// two updates count once.
+	EncoderFifoStalls = 0x01,
+}
+
+impl DiagnosticField {
+	/// Every diagnostic field, ordered by their identifier.
+	pub const ALL: [DiagnosticField; 1] = [
+		DiagnosticField::EncoderFifoStalls,
+	];
+
+	/// Returns the field matching the given identifier, if any.
+	pub fn from_id(id: u8) -> Option<Self> {
+		Self::ALL.into_iter().find(|field| *field as u8 == id)
+	}
+
+	/// Returns the amount of values held by the counter.
+	pub fn count(&self) -> usize {
+		match self {
+			DiagnosticField::EncoderFifoStalls => ENC_GPIO_SIZE,
+		}
+	}
+
+	/// The name of the counter, as used by the host tools.
+	pub fn name(&self) -> &'static str {
+		match self {
+			DiagnosticField::EncoderFifoStalls => "encoder_fifo_stalls",
+		}
+	}
+}
@@ -0,0 +1,26 @@
+//! The configuration protocol of the controller, shared by the firmware and the host tools.
+#![no_std]
+
+pub mod config;
+pub mod diagnostic;
+pub mod option;
+
+pub use crate::config::*;
+pub use crate::diagnostic::*;
+pub use crate::option::*;
+
+
+/// The amount of arcade buttons on the controller.
+pub const BT_SIZE: usize = 7;
+/// The amount of encoders on the controller.
+pub const ENC_GPIO_SIZE: usize = 2;
+/// The amount of points in the custom acceleration curve.
+pub const ACCEL_TABLE_SIZE: usize = 8;
+/// The longest time (in microseconds) between two samples of the encoder pins,
+/// bound by the clock divisor of the encoders' state machines.
+pub const ENC_MAX_FILTER_US: u32 = 3000;
+/// The highest sensitivity of the encoders (in percent of an axis sweep per revolution),
+/// ten axis sweeps per revolution.
+pub const ENC_MAX_SENSITIVITY: u16 = 1000;
+/// The highest gain (in percent) an acceleration curve can apply.
+pub const ACCEL_MAX_GAIN: u16 = 1000;
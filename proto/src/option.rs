@@ -0,0 +1,232 @@
+use crate::*;
+
+
+/// The names of the debounce modes, ordered by their serialized value.
+pub const DEBOUNCE_MODE_NAMES: [&str; 3] = ["none", "hold", "wait"];
+/// The names of the reverse modes, ordered by their serialized value.
+pub const REVERSE_MODE_NAMES: [&str; 4] = ["none", "both", "left", "right"];
+/// The names of the HID modes, ordered by their serialized value.
+pub const HID_MODE_NAMES: [&str; 4] = ["gamepad", "keyboard", "mouse", "composite"];
+/// The names of the HID interfaces, ordered by their serialized value.
+pub const HID_INTERFACE_NAMES: [&str; 3] = ["gamepad", "keyboard", "mouse"];
+/// The names of the idle animations, ordered by their serialized value.
+pub const IDLE_ANIMATION_NAMES: [&str; 4] = ["none", "chase", "pulse", "knight_rider"];
+/// The names of the lighting modes, ordered by their serialized value.
+pub const LIGHTING_MODE_NAMES: [&str; 5] = ["off", "reactive", "inverted", "always_on", "host_driven"];
+/// The names of the RGB effects, ordered by their serialized value.
+pub const RGB_EFFECT_NAMES: [&str; 3] = ["off", "static", "reactive"];
+/// The names of the knob lighting effects, ordered by their serialized value.
+pub const KNOB_LIGHTING_NAMES: [&str; 3] = ["none", "fx_pulse", "sweep"];
+/// The names of the axis resolutions, ordered by their serialized value.
+pub const AXIS_RESOLUTION_NAMES: [&str; 2] = ["standard", "high"];
+/// The names of the axis modes, ordered by their serialized value.
+pub const AXIS_MODE_NAMES: [&str; 2] = ["absolute", "relative"];
+/// The names of the acceleration curves, ordered by their serialized value.
+pub const ACCELERATION_CURVE_NAMES: [&str; 4] = ["none", "linear", "quadratic", "custom"];
+
+
+/// Identifies each option of the controller once serialized.
+/// Options holding several values (one per button, per encoder, etc.) are addressed by index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptionField {
+	// 0x01 was a bool enabling a fixed debounce of the encoders, replaced by `EncoderFilter`.
+	DebounceDuration = 0x02,
+	DebounceMode = 0x03,
+	ReverseEncoders = 0x04,
+	/// Takes effect on the next boot.
+	HidMode = 0x05,
+	/// One key code per button, following the GPIO pin order.
+	KeyMapButtons = 0x06,
+	/// Two key codes per encoder, counter-clockwise first.
+	KeyMapEncoders = 0x07,
+	/// One multiplier per encoder.
+	MouseSensitivity = 0x08,
+	/// One HID interface per button, following the GPIO pin order.
+	RoutingButtons = 0x09,
+	/// One HID interface per encoder.
+	RoutingEncoders = 0x0A,
+	IdleAnimation = 0x0B,
+	/// In seconds.
+	IdleTimeout = 0x0C,
+	LightingMode = 0x0D,
+	/// One brightness per lamp, following the GPIO pin order.
+	LampBrightness = 0x0E,
+	LampFadeDuration = 0x0F,
+	RgbEffect = 0x10,
+	/// One color (0xRRGGBB) per encoder.
+	RgbColors = 0x11,
+	KnobLighting = 0x12,
+	/// One resolution (pulses per revolution) per encoder.
+	EncoderPpr = 0x13,
+	/// One sensitivity per encoder, in percent of an axis sweep per revolution.
+	KnobSensitivity = 0x14,
+	/// Takes effect on the next boot.
+	AxisResolution = 0x15,
+	/// Takes effect on the next boot.
+	AxisMode = 0x16,
+	/// Whether the knobs press virtual gamepad buttons while spinning.
+	KnobButtons = 0x17,
+	/// The time in microseconds a knob button (or keyboard key) stays held after the knob's last count.
+	KnobButtonHold = 0x18,
+	/// The counts a knob has to travel in one direction to press its button (or keyboard key).
+	KnobButtonThreshold = 0x19,
+	AccelerationCurve = 0x1A,
+	/// The gain in percent added at one revolution per second by the linear and quadratic curves.
+	AccelerationFactor = 0x1B,
+	/// The gains in percent of the custom curve, one per revolution per second starting at rest.
+	AccelerationTable = 0x1C,
+	/// The time in microseconds between two samples of the encoder pins. Takes effect on the next boot.
+	EncoderFilter = 0x1D,
+}
+
+impl OptionField {
+	/// Every option field, ordered by their identifier.
+	pub const ALL: [OptionField; 28] = [
+		OptionField::DebounceDuration,
+		OptionField::DebounceMode,
+		OptionField::ReverseEncoders,
+		OptionField::HidMode,
+		OptionField::KeyMapButtons,
+		OptionField::KeyMapEncoders,
+		OptionField::MouseSensitivity,
+		OptionField::RoutingButtons,
+		OptionField::RoutingEncoders,
+		OptionField::IdleAnimation,
+		OptionField::IdleTimeout,
+		OptionField::LightingMode,
+		OptionField::LampBrightness,
+		OptionField::LampFadeDuration,
+		OptionField::RgbEffect,
+		OptionField::RgbColors,
+		OptionField::KnobLighting,
+		OptionField::EncoderPpr,
+		OptionField::KnobSensitivity,
+		OptionField::AxisResolution,
+		OptionField::AxisMode,
+		OptionField::KnobButtons,
+		OptionField::KnobButtonHold,
+		OptionField::KnobButtonThreshold,
+		OptionField::AccelerationCurve,
+		OptionField::AccelerationFactor,
+		OptionField::AccelerationTable,
+		OptionField::EncoderFilter,
+	];
+
+	/// Returns the field matching the given identifier, if any.
+	pub fn from_id(id: u8) -> Option<Self> {
+		Self::ALL.into_iter().find(|field| *field as u8 == id)
+	}
+
+	/// Returns the field matching the given name, if any.
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|field| field.name() == name)
+	}
+
+	/// Returns the amount of values held by the option.
+	pub fn count(&self) -> usize {
+		match self {
+			OptionField::KeyMapButtons
+			| OptionField::RoutingButtons
+			| OptionField::LampBrightness => BT_SIZE,
+			OptionField::KeyMapEncoders => ENC_GPIO_SIZE * 2,
+			OptionField::MouseSensitivity
+			| OptionField::RoutingEncoders
+			| OptionField::RgbColors
+			| OptionField::EncoderPpr
+			| OptionField::KnobSensitivity => ENC_GPIO_SIZE,
+			OptionField::AccelerationTable => ACCEL_TABLE_SIZE,
+			_ => 1,
+		}
+	}
+
+	/// The name of the option, as used by the host tools.
+	pub fn name(&self) -> &'static str {
+		match self {
+			OptionField::EncoderFilter => "encoder_filter",
+			OptionField::DebounceDuration => "debounce_duration",
+			OptionField::DebounceMode => "debounce_mode",
+			OptionField::ReverseEncoders => "reverse_encoders",
+			OptionField::HidMode => "hid_mode",
+			OptionField::KeyMapButtons => "key_map_buttons",
+			OptionField::KeyMapEncoders => "key_map_encoders",
+			OptionField::MouseSensitivity => "mouse_sensitivity",
+			OptionField::RoutingButtons => "routing_buttons",
+			OptionField::RoutingEncoders => "routing_encoders",
+			OptionField::IdleAnimation => "idle_animation",
+			OptionField::IdleTimeout => "idle_timeout",
+			OptionField::LightingMode => "lighting_mode",
+			OptionField::LampBrightness => "lamp_brightness",
+			OptionField::LampFadeDuration => "lamp_fade_duration",
+			OptionField::RgbEffect => "rgb_effect",
+			OptionField::RgbColors => "rgb_colors",
+			OptionField::KnobLighting => "knob_lighting",
+			OptionField::EncoderPpr => "encoder_ppr",
+			OptionField::KnobSensitivity => "knob_sensitivity",
+			OptionField::AxisResolution => "axis_resolution",
+			OptionField::AxisMode => "axis_mode",
+			OptionField::KnobButtons => "knob_buttons",
+			OptionField::KnobButtonHold => "knob_button_hold",
+			OptionField::KnobButtonThreshold => "knob_button_threshold",
+			OptionField::AccelerationCurve => "acceleration_curve",
+			OptionField::AccelerationFactor => "acceleration_factor",
+			OptionField::AccelerationTable => "acceleration_table",
+		}
+	}
+
+	/// The kind of values held by the option.
+	pub fn kind(&self) -> OptionKind {
+		match self {
+			OptionField::KnobButtons => OptionKind::Bool,
+			OptionField::DebounceDuration
+			| OptionField::IdleTimeout
+			| OptionField::LampFadeDuration
+			| OptionField::KnobButtonHold => OptionKind::Integer { min: 0, max: u32::MAX },
+			OptionField::DebounceMode => OptionKind::Enum(&DEBOUNCE_MODE_NAMES),
+			OptionField::ReverseEncoders => OptionKind::Enum(&REVERSE_MODE_NAMES),
+			OptionField::HidMode => OptionKind::Enum(&HID_MODE_NAMES),
+			OptionField::KeyMapButtons
+			| OptionField::KeyMapEncoders
+			| OptionField::MouseSensitivity
+			| OptionField::LampBrightness
+			| OptionField::KnobButtonThreshold => OptionKind::Integer { min: 0, max: u8::MAX as u32 },
+			OptionField::RoutingButtons
+			| OptionField::RoutingEncoders => OptionKind::Enum(&HID_INTERFACE_NAMES),
+			OptionField::IdleAnimation => OptionKind::Enum(&IDLE_ANIMATION_NAMES),
+			OptionField::LightingMode => OptionKind::Enum(&LIGHTING_MODE_NAMES),
+			OptionField::RgbEffect => OptionKind::Enum(&RGB_EFFECT_NAMES),
+			OptionField::RgbColors => OptionKind::Integer { min: 0, max: 0xFFFFFF },
+			OptionField::KnobLighting => OptionKind::Enum(&KNOB_LIGHTING_NAMES),
+			OptionField::EncoderPpr => OptionKind::Integer { min: 1, max: u16::MAX as u32 },
+			OptionField::KnobSensitivity => OptionKind::Integer { min: 1, max: ENC_MAX_SENSITIVITY as u32 },
+			OptionField::AccelerationTable => OptionKind::Integer { min: 1, max: ACCEL_MAX_GAIN as u32 },
+			OptionField::EncoderFilter => OptionKind::Integer { min: 0, max: ENC_MAX_FILTER_US },
+			OptionField::AccelerationFactor => OptionKind::Integer { min: 0, max: u16::MAX as u32 },
+			OptionField::AxisResolution => OptionKind::Enum(&AXIS_RESOLUTION_NAMES),
+			OptionField::AxisMode => OptionKind::Enum(&AXIS_MODE_NAMES),
+			OptionField::AccelerationCurve => OptionKind::Enum(&ACCELERATION_CURVE_NAMES),
+		}
+	}
+}
+
+
+/// Describes how the raw value of an option is interpreted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptionKind {
+	/// Either `0` (false) or `1` (true).
+	Bool,
+	/// Any number within the given bounds (inclusive).
+	Integer { min: u32, max: u32 },
+	/// The position of one of the given names.
+	Enum(&'static [&'static str]),
+}
+
+impl OptionKind {
+	/// Checks whether the raw value can be held by an option of this kind.
+	pub fn is_valid(&self, value: u32) -> bool {
+		match self {
+			OptionKind::Bool => value <= 1,
+			OptionKind::Integer { min, max } => (*min..=*max).contains(&value),
+			OptionKind::Enum(names) => (value as usize) < names.len(),
+		}
+	}
+}
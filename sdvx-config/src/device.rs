@@ -0,0 +1,335 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use pico_sdvx_proto::*;
+use toml::{Table, Value};
+
+use crate::*;
+
+
+/// The amount of times a pending response is read again before giving up.
+pub const PENDING_RETRIES: usize = 100;
+/// The time waited before reading a pending response again.
+pub const PENDING_RETRY_DELAY: Duration = Duration::from_millis(1);
+
+
+/// Moves the feature reports of the configuration protocol between the host and the controller.
+pub trait Transport {
+	/// Writes a feature report (SET_REPORT).
+	fn set_feature(&mut self, report: &[u8; CONFIG_REPORT_SIZE]) -> io::Result<()>;
+
+	/// Reads a feature report back (GET_REPORT).
+	fn get_feature(&mut self) -> io::Result<[u8; CONFIG_REPORT_SIZE]>;
+}
+
+
+/// A controller speaking the configuration protocol.
+pub struct Device<T: Transport> {
+	transport: T,
+}
+
+impl<T: Transport> Device<T> {
+	/// Connects to the controller, making sure it speaks the same version of the protocol.
+	pub fn open(transport: T) -> Result<Self> {
+		let mut device = Self { transport };
+		let version = device.request(ConfigCommand::Version, 0, 0, 0)?;
+
+		if version != CONFIG_PROTOCOL_VERSION {
+			return Err(Error::UnsupportedVersion(version));
+		}
+
+		Ok(device)
+	}
+
+	/// Sends a request and waits for the controller to handle it.
+	///
+	/// Returns the value of the response.
+	pub fn request(&mut self, command: ConfigCommand, field: u8, index: u8, value: u32) -> Result<u32> {
+		let request = ConfigMessage::request(command, field, index, value);
+
+		self.transport.set_feature(&request.to_bytes())?;
+
+		for _ in 0..PENDING_RETRIES {
+			let response = ConfigMessage::from_bytes(&self.transport.get_feature()?).ok_or(Error::InvalidResponse)?;
+
+			// The response always echoes the request it belongs to.
+			if (response.command, response.field, response.index) != (request.command, request.field, request.index) {
+				return Err(Error::InvalidResponse);
+			}
+
+			match ConfigStatus::from_id(response.status) {
+				Some(ConfigStatus::Ok) => return Ok(response.value),
+				Some(ConfigStatus::Pending) => thread::sleep(PENDING_RETRY_DELAY),
+				Some(status) => return Err(Error::Rejected(status)),
+				None => return Err(Error::InvalidResponse),
+			}
+		}
+
+		Err(Error::InvalidResponse)
+	}
+
+	/// Reads every value of an option.
+	pub fn get(&mut self, field: OptionField) -> Result<Vec<u32>> {
+		(0..field.count())
+			.map(|index| self.request(ConfigCommand::Get, field as u8, index as u8, 0))
+			.collect()
+	}
+
+	/// Writes every value of an option. The change is applied immediately, but isn't committed.
+	pub fn set(&mut self, field: OptionField, values: &[u32]) -> Result<()> {
+		for (index, value) in values.iter().enumerate() {
+			self.request(ConfigCommand::Set, field as u8, index as u8, *value)?;
+		}
+
+		Ok(())
+	}
+
+	/// Writes the current options to the controller's flash.
+	pub fn commit(&mut self) -> Result<()> {
+		self.request(ConfigCommand::Commit, 0, 0, 0).map(|_| ())
+	}
+
+	/// Reads every option into a TOML table.
+	pub fn dump(&mut self) -> Result<Table> {
+		let mut table = Table::new();
+
+		for field in OptionField::ALL {
+			table.insert(field.name().to_string(), to_toml(field, &self.get(field)?));
+		}
+
+		Ok(table)
+	}
+
+	/// Reads every diagnostic counter into a TOML table.
+	pub fn diagnostics(&mut self) -> Result<Table> {
+		let mut table = Table::new();
+
+		for field in DiagnosticField::ALL {
+			let values = (0..field.count())
+				.map(|index| self.request(ConfigCommand::Diagnostic, field as u8, index as u8, 0))
+				.map(|value| value.map(|value| Value::Integer(value as i64)))
+				.collect::<Result<Vec<_>>>()?;
+
+			table.insert(field.name().to_string(), Value::Array(values));
+		}
+
+		Ok(table)
+	}
+
+	/// Writes the options found in a TOML table. Options missing from the table are left untouched.
+	///
+	/// The whole table is validated first, nothing is written if any of the options is invalid.
+	pub fn restore(&mut self, table: &Table) -> Result<()> {
+		let options = table
+			.iter()
+			.map(|(name, value)| {
+				let field = OptionField::from_name(name).ok_or_else(|| Error::UnknownOption(name.clone()))?;
+				Ok((field, from_toml(field, value)?))
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		for (field, values) in options {
+			self.set(field, &values)?;
+		}
+
+		Ok(())
+	}
+
+	/// Releases the underlying transport.
+	pub fn into_inner(self) -> T {
+		self.transport
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::*;
+
+	/// Emulates the controller's side of the protocol.
+	#[derive(Default)]
+	struct MockTransport {
+		values: HashMap<(u8, u8), u32>,
+		committed: bool,
+		version: u32,
+		fifo_stalls: [u32; ENC_GPIO_SIZE],
+		/// The amount of reads answered with a pending status before each response.
+		pending_reads: usize,
+		pending: usize,
+		request: Option<ConfigMessage>,
+		writes: usize,
+	}
+
+	impl MockTransport {
+		fn new() -> Self {
+			let mut values = HashMap::new();
+
+			for field in OptionField::ALL {
+				let value = match field.kind() {
+					OptionKind::Integer { min, .. } => min,
+					_ => 0,
+				};
+
+				for index in 0..field.count() {
+					values.insert((field as u8, index as u8), value);
+				}
+			}
+
+			Self { values, version: CONFIG_PROTOCOL_VERSION, ..Default::default() }
+		}
+
+		fn handle(&mut self, request: &ConfigMessage) -> ConfigMessage {
+			let key = (request.field, request.index);
+			let field = OptionField::from_id(request.field);
+
+			match (ConfigCommand::from_id(request.command), field) {
+				(Some(ConfigCommand::Version), _) => request.respond(ConfigStatus::Ok, self.version),
+				(Some(ConfigCommand::Diagnostic), _) => match DiagnosticField::from_id(request.field) {
+					Some(field) if (request.index as usize) < field.count() => {
+						request.respond(ConfigStatus::Ok, self.fifo_stalls[request.index as usize])
+					}
+					Some(_) => request.respond(ConfigStatus::InvalidIndex, 0),
+					None => request.respond(ConfigStatus::UnknownField, 0),
+				},
+				(Some(ConfigCommand::Commit), _) => {
+					self.committed = true;
+					request.respond(ConfigStatus::Ok, 0)
+				}
+				(Some(ConfigCommand::Get | ConfigCommand::Set), None) => request.respond(ConfigStatus::UnknownField, 0),
+				(Some(ConfigCommand::Get | ConfigCommand::Set), _) if !self.values.contains_key(&key) => {
+					request.respond(ConfigStatus::InvalidIndex, 0)
+				}
+				(Some(ConfigCommand::Get), _) => request.respond(ConfigStatus::Ok, self.values[&key]),
+				(Some(ConfigCommand::Set), Some(field)) => {
+					if !field.kind().is_valid(request.value) {
+						return request.respond(ConfigStatus::InvalidValue, 0);
+					}
+
+					self.writes += 1;
+					self.values.insert(key, request.value);
+					request.respond(ConfigStatus::Ok, request.value)
+				}
+				_ => request.respond(ConfigStatus::UnknownCommand, 0),
+			}
+		}
+	}
+
+	impl Transport for MockTransport {
+		fn set_feature(&mut self, report: &[u8; CONFIG_REPORT_SIZE]) -> io::Result<()> {
+			self.request = ConfigMessage::from_bytes(report);
+			self.pending = self.pending_reads;
+
+			Ok(())
+		}
+
+		fn get_feature(&mut self) -> io::Result<[u8; CONFIG_REPORT_SIZE]> {
+			let request = self.request.ok_or(io::ErrorKind::InvalidInput)?;
+
+			if self.pending > 0 {
+				self.pending -= 1;
+				return Ok(request.respond(ConfigStatus::Pending, 0).to_bytes());
+			}
+
+			Ok(self.handle(&request).to_bytes())
+		}
+	}
+
+	#[test]
+	fn other_versions_are_rejected() {
+		let transport = MockTransport { version: CONFIG_PROTOCOL_VERSION + 1, ..MockTransport::new() };
+
+		assert!(matches!(Device::open(transport), Err(Error::UnsupportedVersion(_))));
+	}
+
+	#[test]
+	fn pending_responses_are_read_again() {
+		let mut transport = MockTransport { pending_reads: 3, ..MockTransport::new() };
+		transport.values.insert((OptionField::DebounceDuration as u8, 0), 4000);
+
+		let mut device = Device::open(transport).unwrap();
+
+		assert_eq!(device.get(OptionField::DebounceDuration).unwrap(), vec![4000]);
+	}
+
+	#[test]
+	fn endless_pending_responses_time_out() {
+		let transport = MockTransport { pending_reads: PENDING_RETRIES, ..MockTransport::new() };
+
+		assert!(matches!(Device::open(transport), Err(Error::InvalidResponse)));
+	}
+
+	#[test]
+	fn set_writes_every_index() {
+		let mut device = Device::open(MockTransport::new()).unwrap();
+
+		device.set(OptionField::MouseSensitivity, &[2, 3]).unwrap();
+
+		assert_eq!(device.get(OptionField::MouseSensitivity).unwrap(), vec![2, 3]);
+		assert!(!device.into_inner().committed);
+	}
+
+	#[test]
+	fn rejected_requests_are_reported() {
+		let mut device = Device::open(MockTransport::new()).unwrap();
+
+		assert!(matches!(device.set(OptionField::HidMode, &[4]), Err(Error::Rejected(ConfigStatus::InvalidValue))));
+		assert!(matches!(
+			device.request(ConfigCommand::Get, 0xFF, 0, 0),
+			Err(Error::Rejected(ConfigStatus::UnknownField)),
+		));
+	}
+
+	#[test]
+	fn diagnostics_read_every_counter() {
+		let transport = MockTransport { fifo_stalls: [3, 0], ..MockTransport::new() };
+		let table = Device::open(transport).unwrap().diagnostics().unwrap();
+
+		assert_eq!(table["encoder_fifo_stalls"], Value::Array(vec![Value::Integer(3), Value::Integer(0)]));
+	}
+
+	#[test]
+	fn commit_is_sent() {
+		let mut device = Device::open(MockTransport::new()).unwrap();
+
+		device.commit().unwrap();
+
+		assert!(device.into_inner().committed);
+	}
+
+	#[test]
+	fn dump_and_restore_round_trip() {
+		let mut source = Device::open(MockTransport::new()).unwrap();
+
+		source.set(OptionField::DebounceMode, &[2]).unwrap();
+		source.set(OptionField::HidMode, &[3]).unwrap();
+		source.set(OptionField::KeyMapButtons, &[0x28, 7, 9, 13, 14, 6, 16]).unwrap();
+		source.set(OptionField::RoutingEncoders, &[2, 2]).unwrap();
+
+		let table = source.dump().unwrap();
+		let text = toml::to_string(&table).unwrap();
+
+		assert!(text.contains("hid_mode = \"composite\""));
+
+		let mut target = Device::open(MockTransport::new()).unwrap();
+		target.restore(&text.parse().unwrap()).unwrap();
+
+		assert_eq!(source.into_inner().values, target.into_inner().values);
+	}
+
+	#[test]
+	fn restore_validates_before_writing() {
+		let mut device = Device::open(MockTransport::new()).unwrap();
+
+		let unknown: Table = "hid_mode = \"mouse\"\nlighting = true".parse().unwrap();
+		let invalid: Table = "hid_mode = \"mouse\"\nmouse_sensitivity = [1]".parse().unwrap();
+		let zero_ppr: Table = "hid_mode = \"mouse\"\nencoder_ppr = [0, 360]".parse().unwrap();
+
+		assert!(matches!(device.restore(&unknown), Err(Error::UnknownOption(_))));
+		assert!(matches!(device.restore(&invalid), Err(Error::InvalidValue(..))));
+		assert!(matches!(device.restore(&zero_ppr), Err(Error::InvalidValue(..))));
+		assert_eq!(device.into_inner().writes, 0);
+	}
+}
@@ -0,0 +1,86 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use pico_sdvx_proto::*;
+
+use crate::*;
+
+
+/// The product name reported by the controller.
+pub const DEVICE_NAME: &str = "Pico SDVX Controller";
+
+/// The start of the configuration interface's report descriptor (vendor-defined usage page 0xFF00).
+const CONFIG_DESCRIPTOR_PREFIX: [u8; 3] = [0x06, 0x00, 0xFF];
+
+const HIDRAW_SYSFS_DIR: &str = "/sys/class/hidraw";
+
+const HIDIOCSFEATURE: u64 = 0x06;
+const HIDIOCGFEATURE: u64 = 0x07;
+
+
+/// Accesses the configuration interface through the Linux hidraw driver.
+pub struct Hidraw {
+	file: File,
+}
+
+impl Hidraw {
+	/// Opens the given hidraw device, such as `/dev/hidraw0`.
+	pub fn open(path: &Path) -> Result<Self> {
+		let file = OpenOptions::new().read(true).write(true).open(path)?;
+
+		Ok(Self { file })
+	}
+
+	/// Looks for the configuration interface of a connected controller.
+	pub fn find() -> Result<PathBuf> {
+		for entry in fs::read_dir(HIDRAW_SYSFS_DIR)? {
+			let entry = entry?;
+			let device = entry.path().join("device");
+
+			let uevent = fs::read_to_string(device.join("uevent")).unwrap_or_default();
+			let descriptor = fs::read(device.join("report_descriptor")).unwrap_or_default();
+
+			let is_controller = uevent.lines().any(|line| line.starts_with("HID_NAME=") && line.ends_with(DEVICE_NAME));
+
+			if is_controller && descriptor.starts_with(&CONFIG_DESCRIPTOR_PREFIX) {
+				return Ok(Path::new("/dev").join(entry.file_name()));
+			}
+		}
+
+		Err(Error::NotFound)
+	}
+
+	fn ioctl(&mut self, nr: u64, buf: &mut [u8; CONFIG_REPORT_SIZE + 1]) -> io::Result<()> {
+		// The request is built like the _IOC(_IOC_READ | _IOC_WRITE, 'H', nr, len) macro.
+		let request = (3 << 30) | ((buf.len() as u64) << 16) | ((b'H' as u64) << 8) | nr;
+
+		match unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, buf.as_mut_ptr()) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl Transport for Hidraw {
+	fn set_feature(&mut self, report: &[u8; CONFIG_REPORT_SIZE]) -> io::Result<()> {
+		// The first byte is the report ID, which is always 0 as the controller doesn't use them.
+		let mut buf = [0u8; CONFIG_REPORT_SIZE + 1];
+		buf[1..].copy_from_slice(report);
+
+		self.ioctl(HIDIOCSFEATURE, &mut buf)
+	}
+
+	fn get_feature(&mut self) -> io::Result<[u8; CONFIG_REPORT_SIZE]> {
+		let mut buf = [0u8; CONFIG_REPORT_SIZE + 1];
+
+		self.ioctl(HIDIOCGFEATURE, &mut buf)?;
+
+		let mut report = [0u8; CONFIG_REPORT_SIZE];
+		report.copy_from_slice(&buf[1..]);
+
+		Ok(report)
+	}
+}
+
@@ -0,0 +1,69 @@
+pub mod device;
+pub mod hidraw;
+pub mod value;
+
+pub use crate::device::*;
+pub use crate::hidraw::*;
+pub use crate::value::*;
+
+use std::fmt;
+use std::io;
+
+use pico_sdvx_proto::*;
+
+
+/// The errors produced while configuring the controller.
+#[derive(Debug)]
+pub enum Error {
+	/// The device couldn't be accessed.
+	Io(io::Error),
+	/// No controller was found among the connected devices.
+	NotFound,
+	/// The controller speaks another version of the configuration protocol.
+	UnsupportedVersion(u32),
+	/// The controller rejected the request.
+	Rejected(ConfigStatus),
+	/// The controller's response doesn't match the request, or never became ready.
+	InvalidResponse,
+	/// The option doesn't exist.
+	UnknownOption(String),
+	/// The value can't be held by the option.
+	InvalidValue(&'static str, String),
+	/// The options file couldn't be parsed.
+	Toml(toml::de::Error),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Io(err) => write!(f, "{err}"),
+			Error::NotFound => write!(f, "no controller found, is it plugged in?"),
+			Error::UnsupportedVersion(version) => write!(
+				f,
+				"the controller uses version {version} of the protocol, expected version {CONFIG_PROTOCOL_VERSION}",
+			),
+			Error::Rejected(status) => write!(f, "the controller rejected the request ({status:?})"),
+			Error::InvalidResponse => write!(f, "the controller didn't respond to the request"),
+			Error::UnknownOption(name) => write!(f, "unknown option `{name}`"),
+			Error::InvalidValue(name, reason) => write!(f, "invalid value for `{name}`: {reason}"),
+			Error::Toml(err) => write!(f, "{err}"),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Self {
+		Error::Io(err)
+	}
+}
+
+impl From<toml::de::Error> for Error {
+	fn from(err: toml::de::Error) -> Self {
+		Error::Toml(err)
+	}
+}
+
+
+pub type Result<T> = std::result::Result<T, Error>;
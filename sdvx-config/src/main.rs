@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use pico_sdvx_proto::*;
+use sdvx_config::*;
+use toml::{Table, Value};
+
+
+/// Configures a Pico SDVX controller over USB.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+	/// The hidraw device of the controller, found automatically if omitted.
+	#[arg(short, long)]
+	device: Option<PathBuf>,
+
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Prints the value of an option.
+	Get {
+		option: String,
+	},
+	/// Changes the value of an option, e.g. `set hid_mode keyboard` or `set mouse_sensitivity [2,2]`.
+	Set {
+		option: String,
+		value: String,
+		/// Writes the options to the controller's flash, so they survive a reboot.
+		#[arg(long)]
+		commit: bool,
+	},
+	/// Prints every option as TOML, or writes them to a file.
+	Dump {
+		file: Option<PathBuf>,
+	},
+	/// Prints the controller's diagnostic counters, e.g. the encoder FIFO stalls since boot.
+	Diagnostics,
+	/// Changes the options found in a TOML file.
+	Restore {
+		file: PathBuf,
+		/// Writes the options to the controller's flash, so they survive a reboot.
+		#[arg(long)]
+		commit: bool,
+	},
+}
+
+
+fn main() -> ExitCode {
+	match run(Cli::parse()) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("error: {err}");
+			ExitCode::FAILURE
+		}
+	}
+}
+
+fn run(cli: Cli) -> Result<()> {
+	let path = match cli.device {
+		Some(path) => path,
+		None => Hidraw::find()?,
+	};
+
+	let mut device = Device::open(Hidraw::open(&path)?)?;
+
+	match cli.command {
+		Command::Get { option } => {
+			let field = parse_field(&option)?;
+			let value = to_toml(field, &device.get(field)?);
+
+			println!("{} = {}", field.name(), value);
+		}
+		Command::Set { option, value, commit } => {
+			let field = parse_field(&option)?;
+			let values = from_toml(field, &parse_value(&value))?;
+
+			device.set(field, &values)?;
+
+			if commit { device.commit()?; }
+		}
+		Command::Dump { file } => {
+			let text = toml::to_string(&device.dump()?).expect("options are always valid TOML");
+
+			match file {
+				Some(file) => fs::write(file, text)?,
+				None => print!("{text}"),
+			}
+		}
+		Command::Diagnostics => {
+			print!("{}", toml::to_string(&device.diagnostics()?).expect("counters are always valid TOML"));
+		}
+		Command::Restore { file, commit } => {
+			let table: Table = fs::read_to_string(file)?.parse()?;
+
+			device.restore(&table)?;
+
+			if commit { device.commit()?; }
+		}
+	}
+
+	Ok(())
+}
+
+fn parse_field(name: &str) -> Result<OptionField> {
+	OptionField::from_name(name).ok_or_else(|| Error::UnknownOption(name.to_string()))
+}
+
+/// Parses a value written on the command line as TOML, falling back to a bare string.
+fn parse_value(value: &str) -> Value {
+	format!("value = {value}")
+		.parse::<Table>()
+		.ok()
+		.and_then(|mut table| table.remove("value"))
+		.unwrap_or_else(|| Value::String(value.to_string()))
+}
@@ -0,0 +1,137 @@
+use pico_sdvx_proto::*;
+use toml::Value;
+
+use crate::*;
+
+
+/// Converts the raw values of an option into TOML.
+///
+/// Options holding a single value are converted into a scalar, the rest into an array.
+pub fn to_toml(field: OptionField, values: &[u32]) -> Value {
+	let mut values = values.iter().map(|value| scalar_to_toml(field.kind(), *value));
+
+	if field.count() == 1 {
+		values.next().unwrap_or(Value::Integer(0))
+	}
+	else {
+		Value::Array(values.collect())
+	}
+}
+
+/// Converts a TOML value into the raw values of an option.
+///
+/// Enumerations accept both their names and their raw values.
+pub fn from_toml(field: OptionField, value: &Value) -> Result<Vec<u32>> {
+	if field.count() == 1 {
+		return Ok(vec![scalar_from_toml(field, value)?]);
+	}
+
+	match value {
+		Value::Array(values) if values.len() == field.count() => {
+			values.iter().map(|value| scalar_from_toml(field, value)).collect()
+		}
+		_ => Err(Error::InvalidValue(field.name(), format!("expected an array of {} values", field.count()))),
+	}
+}
+
+
+fn scalar_to_toml(kind: OptionKind, value: u32) -> Value {
+	match kind {
+		OptionKind::Bool => Value::Boolean(value != 0),
+		OptionKind::Integer { .. } => Value::Integer(value as i64),
+		OptionKind::Enum(names) => match names.get(value as usize) {
+			Some(name) => Value::String(name.to_string()),
+			None => Value::Integer(value as i64),
+		},
+	}
+}
+
+fn scalar_from_toml(field: OptionField, value: &Value) -> Result<u32> {
+	let kind = field.kind();
+	let invalid = |reason: String| Error::InvalidValue(field.name(), reason);
+
+	let raw = match (kind, value) {
+		(OptionKind::Bool, Value::Boolean(value)) => *value as u32,
+		(OptionKind::Enum(names), Value::String(name)) => match names.iter().position(|n| n == name) {
+			Some(position) => position as u32,
+			None => return Err(invalid(format!("expected one of {}", names.join(", ")))),
+		},
+		(_, Value::Integer(value)) => u32::try_from(*value).map_err(|_| invalid(format!("{value} is out of range")))?,
+		(OptionKind::Bool, _) => return Err(invalid("expected a boolean".to_string())),
+		(OptionKind::Integer { .. }, _) => return Err(invalid("expected an integer".to_string())),
+		(OptionKind::Enum(names), _) => return Err(invalid(format!("expected one of {}", names.join(", ")))),
+	};
+
+	if !kind.is_valid(raw) {
+		return Err(invalid(format!("{raw} is out of range")));
+	}
+
+	Ok(raw)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn scalars_round_trip() {
+		let cases = [
+			(OptionField::KnobButtons, 1, Value::Boolean(true)),
+			(OptionField::DebounceDuration, 4000, Value::Integer(4000)),
+			(OptionField::HidMode, 3, Value::String("composite".to_string())),
+		];
+
+		for (field, raw, value) in cases {
+			assert_eq!(to_toml(field, &[raw]), value);
+			assert_eq!(from_toml(field, &value).unwrap(), vec![raw]);
+		}
+	}
+
+	#[test]
+	fn arrays_round_trip() {
+		let raw = [0, 2];
+		let value = to_toml(OptionField::RoutingEncoders, &raw);
+
+		assert_eq!(value, Value::Array(vec![Value::String("gamepad".to_string()), Value::String("mouse".to_string())]));
+		assert_eq!(from_toml(OptionField::RoutingEncoders, &value).unwrap(), raw);
+	}
+
+	#[test]
+	fn enums_accept_raw_values() {
+		assert_eq!(from_toml(OptionField::ReverseEncoders, &Value::Integer(2)).unwrap(), vec![2]);
+	}
+
+	#[test]
+	fn invalid_values_are_rejected() {
+		let mut keys = vec![Value::Integer(0x28); BT_SIZE];
+		keys[BT_SIZE - 1] = Value::Integer(0x100);
+
+		let cases = [
+			(OptionField::KnobButtons, Value::Integer(2)),
+			(OptionField::EncoderFilter, Value::Integer(ENC_MAX_FILTER_US as i64 + 1)),
+			(OptionField::KnobSensitivity, Value::Array(vec![Value::Integer(0), Value::Integer(100)])),
+			(OptionField::DebounceMode, Value::String("eager".to_string())),
+			(OptionField::DebounceDuration, Value::Integer(-1)),
+			(OptionField::KeyMapButtons, Value::Array(keys)),
+			(OptionField::MouseSensitivity, Value::Array(vec![Value::Integer(256), Value::Integer(1)])),
+		];
+
+		for (field, value) in cases {
+			assert!(matches!(from_toml(field, &value), Err(Error::InvalidValue(..))), "{value} accepted by {field:?}");
+		}
+	}
+
+	#[test]
+	fn wrong_shapes_are_rejected() {
+		let cases = [
+			(OptionField::KeyMapButtons, Value::Integer(0x28)),
+			(OptionField::MouseSensitivity, Value::Array(vec![Value::Integer(1)])),
+			(OptionField::HidMode, Value::Array(vec![Value::String("gamepad".to_string())])),
+		];
+
+		for (field, value) in cases {
+			assert!(matches!(from_toml(field, &value), Err(Error::InvalidValue(..))), "{value} accepted by {field:?}");
+		}
+	}
+}
@@ -0,0 +1,195 @@
+use rp_pico as bsp;
+
+use bsp::hal;
+
+use crate::{ACCEL_MAX_GAIN, ACCEL_TABLE_SIZE};
+
+
+/// The gain leaving the encoders' movement untouched, gains being in percent.
+pub const ACCEL_GAIN_SCALE: u16 = 100;
+/// The default gain added by the linear and quadratic curves at one revolution per second.
+pub const ACCEL_DEFAULT_FACTOR: u16 = 50;
+/// The speed (in hundredths of a revolution per second) between two points of the custom curve.
+pub const ACCEL_TABLE_STEP: u32 = 100;
+/// The default gains of the custom curve, from rest up to 7 revolutions per second.
+pub const ACCEL_DEFAULT_TABLE: [u16; ACCEL_TABLE_SIZE] = [75, 100, 110, 125, 150, 200, 250, 300];
+/// The time (in microseconds) without a count change after which a knob is considered at rest.
+pub const ACCEL_REST_DURATION_US: u64 = 50_000;
+
+
+/// Determines how the encoders' movement is scaled by their speed.
+/// Default is [`AccelerationCurve::None`].
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum AccelerationCurve {
+	/// The movement is reported as is, whatever the speed.
+	#[default]
+	None = 0x00,
+	/// The gain grows with the speed.
+	Linear = 0x01,
+	/// The gain grows with the square of the speed, keeping slow turns close to untouched.
+	Quadratic = 0x02,
+	/// The gain is interpolated from a table of gains, one per revolution per second.
+	Custom = 0x03,
+}
+
+impl AccelerationCurve {
+	/// Every acceleration curve, ordered by their serialized value.
+	pub const ALL: [AccelerationCurve; 4] = [
+		AccelerationCurve::None,
+		AccelerationCurve::Linear,
+		AccelerationCurve::Quadratic,
+		AccelerationCurve::Custom,
+	];
+
+	/// Returns the gain (in percent, see [`ACCEL_GAIN_SCALE`]) to apply to the movement of a knob
+	/// spinning at the given speed, in hundredths of a revolution per second.
+	///
+	/// `factor` is the gain added at one revolution per second by the linear and quadratic curves,
+	/// and `table` holds the gains of the custom curve every [`ACCEL_TABLE_STEP`].
+	pub fn gain(&self, speed: u32, factor: u16, table: &[u16; ACCEL_TABLE_SIZE]) -> u16 {
+		let step = ACCEL_TABLE_STEP as u64;
+		let speed = speed as u64;
+
+		let gain = match self {
+			AccelerationCurve::None => return ACCEL_GAIN_SCALE,
+			AccelerationCurve::Linear => ACCEL_GAIN_SCALE as u64 + factor as u64 * speed / step,
+			AccelerationCurve::Quadratic => {
+				let squared = speed.saturating_mul(speed);
+				ACCEL_GAIN_SCALE as u64 + (factor as u64).saturating_mul(squared) / (step * step)
+			}
+			AccelerationCurve::Custom => {
+				let index = (speed / step) as usize;
+
+				match (table.get(index), table.get(index + 1)) {
+					(Some(low), Some(high)) => {
+						let (low, high) = (*low as i64, *high as i64);
+						let offset = (speed % step) as i64;
+
+						(low + (high - low) * offset / step as i64) as u64
+					}
+					_ => table[ACCEL_TABLE_SIZE - 1] as u64,
+				}
+			}
+		};
+
+		gain.clamp(1, ACCEL_MAX_GAIN as u64) as u16
+	}
+}
+
+
+/// Tracks the speed of a knob from the times its count changes.
+#[derive(Default)]
+pub struct KnobVelocity {
+	/// The count at the last change.
+	prev_count: u32,
+	/// The moment of the last count change.
+	last_change: Option<hal::timer::Instant>,
+	/// The smoothed speed, in hundredths of a revolution per second.
+	speed: u32,
+}
+
+impl KnobVelocity {
+	/// Registers the knob's count at the given moment, `pulse` being the counts of a revolution.
+	///
+	/// Returns the knob's speed in hundredths of a revolution per second.
+	pub fn update(&mut self, count: u32, now: hal::timer::Instant, pulse: i32) -> u32 {
+		let elapsed = self.last_change
+			.and_then(|last_change| now.checked_duration_since(last_change))
+			.map(|elapsed| elapsed.to_micros().min(ACCEL_REST_DURATION_US))
+			.unwrap_or(ACCEL_REST_DURATION_US);
+
+		let counts = count.wrapping_sub(self.prev_count) as i32;
+
+		if counts == 0 {
+			// No change for a while means the knob stopped.
+			if elapsed >= ACCEL_REST_DURATION_US { self.speed = 0; }
+
+			return self.speed;
+		}
+
+		// Counts per microsecond, converted to hundredths of a revolution per second.
+		let speed = counts.unsigned_abs() as u64 * 100_000_000 / (elapsed.max(1) * pulse.max(1) as u64);
+
+		// Averaging with the previous speed smooths out the jitter of the count changes.
+		self.speed = ((self.speed as u64 + speed.min(u32::MAX as u64)) / 2) as u32;
+		self.prev_count = count;
+		self.last_change = Some(now);
+
+		self.speed
+	}
+
+	/// Returns the knob's last measured speed, in hundredths of a revolution per second.
+	pub fn speed(&self) -> u32 {
+		self.speed
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::at;
+
+	/// The counts of a revolution at the default resolution.
+	const PULSE: i32 = 1440;
+
+	#[test]
+	fn speed_follows_the_count_changes() {
+		let mut velocity = KnobVelocity::default();
+
+		// One revolution per second is a count every ~694µs.
+		velocity.update(0, at(0), PULSE);
+
+		for i in 1..=100u64 {
+			velocity.update(i as u32 * 12, at(i * 8333), PULSE);
+		}
+
+		assert!((99..=101).contains(&velocity.speed()), "{}", velocity.speed());
+
+		// Backwards is just as fast.
+		for i in 1..=100u64 {
+			velocity.update(1200 - i as u32 * 12, at(833_300 + i * 8333), PULSE);
+		}
+
+		assert!((99..=101).contains(&velocity.speed()), "{}", velocity.speed());
+	}
+
+	#[test]
+	fn speed_drops_once_the_knob_rests() {
+		let mut velocity = KnobVelocity::default();
+
+		velocity.update(0, at(0), PULSE);
+		velocity.update(144, at(10_000), PULSE);
+
+		assert!(velocity.speed() > 0);
+		assert!(velocity.update(144, at(59_999), PULSE) > 0);
+		assert_eq!(velocity.update(144, at(60_000), PULSE), 0);
+	}
+
+	#[test]
+	fn curves_apply_their_gain() {
+		let table = ACCEL_DEFAULT_TABLE;
+
+		for speed in [0, 50, 100, 1000] {
+			assert_eq!(AccelerationCurve::None.gain(speed, 50, &table), 100);
+		}
+
+		assert_eq!(AccelerationCurve::Linear.gain(0, 50, &table), 100);
+		assert_eq!(AccelerationCurve::Linear.gain(200, 50, &table), 200);
+		assert_eq!(AccelerationCurve::Quadratic.gain(50, 100, &table), 125);
+		assert_eq!(AccelerationCurve::Quadratic.gain(300, 100, &table), 1000);
+		assert_eq!(AccelerationCurve::Quadratic.gain(u32::MAX, u16::MAX, &table), ACCEL_MAX_GAIN);
+	}
+
+	#[test]
+	fn custom_curve_interpolates_its_table() {
+		let table = [50, 100, 200, 200, 100, 300, 400, 500];
+
+		assert_eq!(AccelerationCurve::Custom.gain(0, 0, &table), 50);
+		assert_eq!(AccelerationCurve::Custom.gain(50, 0, &table), 75);
+		assert_eq!(AccelerationCurve::Custom.gain(150, 0, &table), 150);
+		assert_eq!(AccelerationCurve::Custom.gain(350, 0, &table), 150);
+		assert_eq!(AccelerationCurve::Custom.gain(700, 0, &table), 500);
+		assert_eq!(AccelerationCurve::Custom.gain(u32::MAX, 0, &table), 500);
+	}
+}
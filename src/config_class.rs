@@ -0,0 +1,146 @@
+use usb_device::class_prelude::*;
+use usb_device::Result;
+use usbd_hid::descriptor::SerializedDescriptor;
+
+use crate::*;
+
+
+const USB_CLASS_HID: u8 = 0x03;
+
+const HID_DESC_DESCTYPE_HID: u8 = 0x21;
+const HID_DESC_DESCTYPE_HID_REPORT: u8 = 0x22;
+const HID_DESC_SPEC_1_10: [u8; 2] = [0x10, 0x01];
+
+const HID_REQ_GET_REPORT: u8 = 0x01;
+const HID_REQ_SET_REPORT: u8 = 0x09;
+const HID_REQ_SET_IDLE: u8 = 0x0A;
+
+const HID_REPORT_TYPE_FEATURE: u8 = 0x03;
+
+
+/// USB HID interface exposing the configuration protocol through the [`ConfigReport`] feature report.
+///
+/// Unlike the other HID interfaces, feature reports can be read back by the host (GET_REPORT),
+/// which is why this class doesn't rely on [`usbd_hid::hid_class::HIDClass`].
+pub struct ConfigClass<'a, B: UsbBus> {
+	if_num: InterfaceNumber,
+	/// Unused, but required by the HID specification.
+	in_ep: EndpointIn<'a, B>,
+	/// The last request written by the host, waiting to be handled.
+	request: Option<ConfigMessage>,
+	/// The report returned to the host when it reads the feature report.
+	response: [u8; CONFIG_REPORT_SIZE],
+}
+
+impl<'a, B: UsbBus> ConfigClass<'a, B> {
+	/// Creates a new configuration interface with the provided UsbBus.
+	pub fn new(alloc: &'a UsbBusAllocator<B>, poll_ms: u8) -> Self {
+		Self {
+			if_num: alloc.interface(),
+			in_ep: alloc.interrupt(CONFIG_REPORT_SIZE as u16, poll_ms),
+			request: None,
+			response: [0; CONFIG_REPORT_SIZE],
+		}
+	}
+
+	/// Takes the last request written by the host, if it hasn't been handled yet.
+	pub fn pull_request(&mut self) -> Option<ConfigMessage> {
+		self.request.take()
+	}
+
+	/// Sets the response returned to the host the next time it reads the feature report.
+	pub fn push_response(&mut self, response: &ConfigMessage) {
+		self.response = response.to_bytes();
+	}
+
+	/// The contents of the HID descriptor, following the descriptor's length and type.
+	fn hid_descriptor(&self) -> [u8; 7] {
+		let len = ConfigReport::desc().len();
+
+		[
+			HID_DESC_SPEC_1_10[0],
+			HID_DESC_SPEC_1_10[1],
+			0, // Country code (not supported).
+			1, // Number of class descriptors.
+			HID_DESC_DESCTYPE_HID_REPORT,
+			(len & 0xFF) as u8,
+			(len >> 8 & 0xFF) as u8,
+		]
+	}
+}
+
+impl<B: UsbBus> UsbClass<B> for ConfigClass<'_, B> {
+	fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+		writer.interface(self.if_num, USB_CLASS_HID, 0, 0)?;
+		writer.write(HID_DESC_DESCTYPE_HID, &self.hid_descriptor())?;
+		writer.endpoint(&self.in_ep)
+	}
+
+	fn control_in(&mut self, xfer: ControlIn<B>) {
+		let req = *xfer.request();
+
+		// Ignore the requests that aren't meant for this interface.
+		if !(req.recipient == control::Recipient::Interface && req.index == u8::from(self.if_num) as u16) {
+			return;
+		}
+
+		match (req.request_type, req.request) {
+			(control::RequestType::Standard, control::Request::GET_DESCRIPTOR) => {
+				match (req.value >> 8) as u8 {
+					HID_DESC_DESCTYPE_HID_REPORT => {
+						xfer.accept_with_static(ConfigReport::desc()).ok();
+					}
+					HID_DESC_DESCTYPE_HID => {
+						let mut descriptor = [0u8; 9];
+
+						descriptor[0] = descriptor.len() as u8;
+						descriptor[1] = HID_DESC_DESCTYPE_HID;
+						descriptor[2..].copy_from_slice(&self.hid_descriptor());
+
+						xfer.accept_with(&descriptor).ok();
+					}
+					_ => {}
+				}
+			}
+			(control::RequestType::Class, HID_REQ_GET_REPORT) if (req.value >> 8) as u8 == HID_REPORT_TYPE_FEATURE => {
+				xfer.accept_with(&self.response).ok();
+			}
+			(control::RequestType::Class, _) => {
+				xfer.reject().ok();
+			}
+			_ => {}
+		}
+	}
+
+	fn control_out(&mut self, xfer: ControlOut<B>) {
+		let req = *xfer.request();
+
+		// Ignore the requests that aren't meant for this interface.
+		if !(req.recipient == control::Recipient::Interface && req.index == u8::from(self.if_num) as u16) {
+			return;
+		}
+
+		match (req.request_type, req.request) {
+			(control::RequestType::Class, HID_REQ_SET_IDLE) => {
+				xfer.accept().ok();
+			}
+			(control::RequestType::Class, HID_REQ_SET_REPORT) if (req.value >> 8) as u8 == HID_REPORT_TYPE_FEATURE => {
+				match ConfigMessage::from_bytes(xfer.data()) {
+					Some(request) => {
+						// Until the request is handled, the host is told to keep waiting.
+						self.response = request.respond(ConfigStatus::Pending, 0).to_bytes();
+						self.request = Some(request);
+						xfer.accept().ok();
+					}
+					None => {
+						xfer.reject().ok();
+					}
+				}
+			}
+			(control::RequestType::Class, _) => {
+				xfer.reject().ok();
+			}
+			_ => {}
+		}
+	}
+}
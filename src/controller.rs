@@ -1,27 +1,57 @@
 use rp_pico as bsp;
 
 use bsp::hal;
+use hal::dma;
 use hal::gpio;
 use hal::pac;
 use hal::pio;
 
 use crate::*;
 
+use embedded_hal::delay::DelayNs;
 use embedded_hal::digital::{InputPin, OutputPin};
-use hal::fugit::MicrosDurationU64;
+use hal::fugit::{MicrosDurationU32, MicrosDurationU64};
+use usbd_hid::descriptor::KeyboardUsage;
 
 
-/// The amount of arcade buttons on the controller.
-pub const BT_SIZE: usize = 7;
 /// The duration (in microseconds) for debouncing the microswitches.
 pub const SW_DEFAULT_DEBOUNCE_DURATION_US: u64 = 4000;
-/// The amount of encoders on the controller.
-pub const ENC_GPIO_SIZE: usize = 2;
-/// The resolution of the encoders in a pulses per revolution metric.
-pub const ENC_PPR: i32 = 360;
-/// The number of pulses needed to complete a full revolution.
-/// Alias the number of reports per revolution.
-pub const ENC_PULSE: i32 = ENC_PPR * 4;
+/// The frequency of the system clock set up by `init_clocks_and_plls`, which drives the PIO.
+pub const SYS_CLOCK_HZ: u32 = 125_000_000;
+/// The cycles taken by the encoders' state machines to sample the pins once, see `encoders.pio`.
+pub const ENC_FILTER_LOOP_CYCLES: u32 = 6;
+/// The time (in milliseconds) the onboard LED stays on and off for each blink
+/// signaling a misconfigured encoder.
+pub const ENC_PIN_ERROR_BLINK_MS: u32 = 150;
+/// The pause (in milliseconds) between two series of blinks signaling a misconfigured encoder.
+pub const ENC_PIN_ERROR_PAUSE_MS: u32 = 1000;
+/// The default resolution of the encoders in a pulses per revolution metric.
+pub const ENC_DEFAULT_PPR: u16 = 360;
+/// The number of counts reported per pulse, as the PIO program counts every edge of both channels.
+pub const ENC_COUNTS_PER_PULSE: i32 = 4;
+/// The sensitivity at which a full revolution of an encoder sweeps its axis once.
+/// Sensitivities are expressed in percent of this.
+pub const ENC_SENSITIVITY_SCALE: i32 = 100;
+/// The default sensitivity of the encoders, one axis sweep per revolution.
+pub const ENC_DEFAULT_SENSITIVITY: u16 = ENC_SENSITIVITY_SCALE as u16;
+/// The default multiplier applied to the encoders' movement in [`HidMode::Mouse`].
+pub const MOUSE_DEFAULT_SENSITIVITY: u8 = 1;
+/// The HID mode selected by holding each button while plugging in the controller, following the
+/// GPIO pin order. The selection only lasts for the current session.
+pub const BOOT_HID_MODES: [Option<HidMode>; BT_SIZE] = [
+	None,						// START
+	Some(HidMode::Keyboard),	// BT-A
+	Some(HidMode::Mouse),		// BT-B
+	Some(HidMode::Composite),	// BT-C
+	Some(HidMode::Gamepad),		// BT-D
+	None,						// FX-L
+	None,						// FX-R
+];
+/// The time (in microseconds) the lamps stay under the host's control after its last output report.
+/// Once elapsed, the lamps go back to reacting to the buttons.
+pub const HOST_LIGHTS_TIMEOUT_US: u64 = 1_000_000;
+/// The time (in microseconds) given to the switches' pull-ups to settle before reading them at boot.
+pub const BOOT_SETTLE_DURATION_US: u32 = 1000;
 /// The speed at which the controller reports to the host.
 /// Higher values produce more latency, but generate less CPU stress.
 pub const USB_HID_POLL_RATE_MS: u8 = 1; 
@@ -37,7 +67,6 @@ static mut CONTROLLER: Option<SDVXController> = None;
 // [VOL-L (A, B)] -> [VOL-R (A, B)]
 
 
-// TODO: Add Keyboard and Mouse reporting mode.
 /// Sound Voltex controller.
 pub struct SDVXController {
 	buttons: [Button; BT_SIZE],
@@ -45,16 +74,41 @@ pub struct SDVXController {
 
 	options: SDVXControllerOptions,
 	report: GamepadReport,
-
-	rx_l: Option<pio::Rx<pio::PIO0SM0>>,
-	rx_r: Option<pio::Rx<pio::PIO0SM1>>,
+	boot_hid_mode: Option<HidMode>,
+	/// The HID mode of the running session, fixed once the controller is started.
+	session_hid_mode: Option<HidMode>,
+	/// The axis resolution of the running session, fixed once the controller is started.
+	session_axis_resolution: Option<AxisResolution>,
+	/// The axis mode of the running session, fixed once the controller is started.
+	session_axis_mode: Option<AxisMode>,
+	/// The on-device configuration menu, while it's open.
+	menu: Option<ConfigMenu>,
+	/// The moment START started being held, to open the configuration menu.
+	menu_hold_start: Option<hal::timer::Instant>,
+	/// The lamps last set by the host and when they were received.
+	host_lamps: Option<(u8, hal::timer::Instant)>,
+	idle: IdleTracker,
+
+	pwm: pac::PWM,
+	rgb_pin: DynPio1Pin,
+	rgb: Option<Ws2812>,
+	/// The moment the last frame was sent to the RGB strip.
+	rgb_frame_time: Option<hal::timer::Instant>,
+
+	transfer_l: Option<EncoderTransfer<dma::CH0, pio::SM0>>,
+	transfer_r: Option<EncoderTransfer<dma::CH1, pio::SM1>>,
+	counts: [EncoderCount; ENC_GPIO_SIZE],
 
 	timer: hal::Timer,
 }
 
 impl SDVXController {
 	/// Initializes the components used by the controller.
-	pub fn init(pins: bsp::Pins, timer: hal::Timer) {
+	/// The PWM peripheral must be out of reset, its slices are set up to drive the lamps.
+	///
+	/// Holding one of the buttons listed in [`BOOT_HID_MODES`] during initialization overrides the
+	/// HID mode for the current session.
+	pub fn init(pins: bsp::Pins, pwm: pac::PWM, mut timer: hal::Timer) {
 		// Abort if the controller has already been initialized.
 		if unsafe { CONTROLLER.is_some() } { return; }
 
@@ -79,24 +133,29 @@ impl SDVXController {
 	
 		// These are the lamp holders/LEDs of the buttons.
 	
-		let led_start_pin = pins.gpio1.into_push_pull_output().into_dyn_pin();
-		let led_bt_a_pin = pins.gpio3.into_push_pull_output().into_dyn_pin();
-		let led_bt_b_pin = pins.gpio5.into_push_pull_output().into_dyn_pin();
-		let led_bt_c_pin = pins.gpio7.into_push_pull_output().into_dyn_pin();
-		let led_bt_d_pin = pins.gpio9.into_push_pull_output().into_dyn_pin();
-		let led_fx_l_pin = pins.gpio11.into_push_pull_output().into_dyn_pin();
-		let led_fx_r_pin = pins.gpio13.into_push_pull_output().into_dyn_pin();
+		let led_start_pin: DynPwmPin = pins.gpio1.reconfigure().into_dyn_pin();
+		let led_bt_a_pin: DynPwmPin = pins.gpio3.reconfigure().into_dyn_pin();
+		let led_bt_b_pin: DynPwmPin = pins.gpio5.reconfigure().into_dyn_pin();
+		let led_bt_c_pin: DynPwmPin = pins.gpio7.reconfigure().into_dyn_pin();
+		let led_bt_d_pin: DynPwmPin = pins.gpio9.reconfigure().into_dyn_pin();
+		let led_fx_l_pin: DynPwmPin = pins.gpio11.reconfigure().into_dyn_pin();
+		let led_fx_r_pin: DynPwmPin = pins.gpio13.reconfigure().into_dyn_pin();
 	
 		// These are the encoders GPIO configurations.
+		// Pin B of each encoder must be the GPIO right after its pin A (e.g. GPIO14 and GPIO15).
 	
 		let enc_l_pin_a: DynPio0Pin = pins.gpio14.reconfigure().into_dyn_pin();
 		let enc_l_pin_b: DynPio0Pin = pins.gpio15.reconfigure().into_dyn_pin();
 		let enc_r_pin_a: DynPio0Pin = pins.gpio16.reconfigure().into_dyn_pin();
 		let enc_r_pin_b: DynPio0Pin = pins.gpio17.reconfigure().into_dyn_pin();
+
+		// This is the data pin of the WS2812 RGB strip.
+
+		let rgb_pin: DynPio1Pin = pins.gpio22.reconfigure().into_dyn_pin();
 	
 		/* ~~ GPIO/PINOUT CONFIGURATION END ~~ */
 
-		let buttons: [Button; BT_SIZE] = [
+		let mut buttons: [Button; BT_SIZE] = [
 			Button::new(sw_start_pin, led_start_pin),	// 0
 			Button::new(sw_bt_a_pin, led_bt_a_pin),		// 1
 			Button::new(sw_bt_b_pin, led_bt_b_pin),		// 2
@@ -111,14 +170,48 @@ impl SDVXController {
 			Encoder::new(enc_r_pin_a, enc_r_pin_b),		// 1
 		];
 
+		// The encoder program can't read pins that aren't adjacent, see `encoder_pins_adjacent`.
+		if let Some(index) = encoders.iter().position(|encoder| !encoder.has_adjacent_pins()) {
+			signal_encoder_pin_error(&mut pico_led_pin, &mut timer, index);
+		}
+
+		// Every lamp is driven by the channel of the PWM slice wired to its pin.
+		for button in buttons.iter() {
+			let slice = pwm.ch(pwm_slice(button.led_pin.id().num));
+
+			slice.top().write(|w| unsafe { w.top().bits(LAMP_PWM_TOP) });
+			slice.div().write(|w| unsafe { w.int().bits(LAMP_PWM_DIVIDER) });
+			slice.csr().modify(|_, w| w.en().set_bit());
+		}
+
+		// Give the pull-ups some time to settle, then check for a boot-time HID mode selection.
+		timer.delay_us(BOOT_SETTLE_DURATION_US);
+
+		let boot_hid_mode = buttons.iter_mut()
+			.zip(BOOT_HID_MODES)
+			.find_map(|(button, hid_mode)| if button.is_pressed() { hid_mode } else { None });
+
 		unsafe {
 			CONTROLLER = Some(Self {
 				buttons,
 				encoders,
-				options: SDVXControllerOptions::default(),
+				options: storage::load(),
 				report: GamepadReport::default(),
-				rx_l: None,
-				rx_r: None,
+				boot_hid_mode,
+				session_hid_mode: None,
+				session_axis_resolution: None,
+				session_axis_mode: None,
+				menu: None,
+				menu_hold_start: None,
+				host_lamps: None,
+				idle: IdleTracker::default(),
+				pwm,
+				rgb_pin,
+				rgb: None,
+				rgb_frame_time: None,
+				transfer_l: None,
+				transfer_r: None,
+				counts: [EncoderCount::new(0), EncoderCount::new(1)],
 				timer,
 			});
 		}
@@ -126,15 +219,18 @@ impl SDVXController {
 		pico_led_pin.set_high().unwrap();
 	}
 
-	/// Loads and starts the given PIO program for the encoders. One state machine per encoder.
+	/// Loads and starts the given PIO program for the encoders. One state machine per encoder,
+	/// each drained by one of the given DMA channels.
 	pub fn start(
 		&mut self,
 		program: &pio::InstalledProgram<pac::PIO0>,
 		sm0: pio::UninitStateMachine<pio::PIO0SM0>,
 		sm1: pio::UninitStateMachine<pio::PIO0SM1>,
+		ch0: dma::Channel<dma::CH0>,
+		ch1: dma::Channel<dma::CH1>,
 	) {
 		// Abort if the encoders have already been initialized.
-		if self.rx_l.is_some() || self.rx_r.is_some() { return; }
+		if self.transfer_l.is_some() || self.transfer_r.is_some() { return; }
 
 		let enc_l = self.encoders[0].pins();
 		let enc_r = self.encoders[1].pins();
@@ -144,7 +240,7 @@ impl SDVXController {
 			sm0,
 			enc_l.0.id().num,
 			enc_l.1.id().num,
-			self.options.debounce_encoders,
+			self.options.encoder_filter,
 		);
 
 		let (sm1, rx1, _) = load_encoder_program(
@@ -152,14 +248,35 @@ impl SDVXController {
 			sm1,
 			enc_r.0.id().num,
 			enc_r.1.id().num,
-			self.options.debounce_encoders,
+			self.options.encoder_filter,
 		);
 
 		// Synchronizes both state machines and starts them at the same time.
 		sm0.with(sm1).start();
 
-		self.rx_l = Some(rx0);
-		self.rx_r = Some(rx1);
+		// The USB interfaces are set up by now, changes to the HID mode wait for the next boot.
+		self.session_hid_mode = Some(self.hid_mode());
+		self.session_axis_resolution = Some(self.axis_resolution());
+		self.session_axis_mode = Some(self.axis_mode());
+
+		self.transfer_l = Some(start_encoder_transfer(ch0, rx0, self.counts[0]));
+		self.transfer_r = Some(start_encoder_transfer(ch1, rx1, self.counts[1]));
+	}
+
+	/// Loads and starts the given WS2812 program for the RGB strip.
+	pub fn start_rgb(
+		&mut self,
+		program: &pio::InstalledProgram<pac::PIO1>,
+		sm0: pio::UninitStateMachine<pio::PIO1SM0>,
+	) {
+		// Abort if the RGB strip has already been initialized.
+		if self.rgb.is_some() { return; }
+
+		let (sm0, tx0) = load_ws2812_program(unsafe { program.share() }, sm0, self.rgb_pin.id().num);
+
+		sm0.start();
+
+		self.rgb = Some(Ws2812::new(tx0));
 	}
 
 	/// Wrapper for all update methods. It is recommended to call this method instead of
@@ -167,7 +284,9 @@ impl SDVXController {
 	pub fn update(&mut self) {
 		self.update_encoders();
 		self.update_inputs();
+		self.update_menu();
 		self.update_lights();
+		self.update_rgb();
 	}
 
 	/// Updates the HID report with the current state of the encoders.
@@ -175,25 +294,74 @@ impl SDVXController {
 	/// Note: If the [`SDVXController::start`] method hasn't been called, this won't work.
 	pub fn update_encoders(&mut self) {
 		// Abort if the encoders have not been started.
-		if self.rx_l.is_none() || self.rx_r.is_none() { return; }
+		if self.transfer_l.is_none() || self.transfer_r.is_none() { return; }
+
+		self.transfer_l = self.transfer_l.take().map(renew_encoder_transfer);
+		self.transfer_r = self.transfer_r.take().map(renew_encoder_transfer);
 
-		let rx_l = self.rx_l.as_mut().unwrap();
-		let rx_r = self.rx_r.as_mut().unwrap();
+		let now = self.timer.get_counter();
+		let routes = [self.encoder_route(0), self.encoder_route(1)];
+		let stalls = take_encoder_stalls();
 		let reverse = self.options.reverse_encoders.state();
+		let sensitivity = self.options.mouse_sensitivity;
+		let pulses = [self.options.encoder_pulses(0), self.options.encoder_pulses(1)];
+		let knob_sensitivity = self.options.knob_sensitivity;
+		let counts = [self.counts[0].read(), self.counts[1].read()];
+		let in_menu = self.menu.is_some();
+		let gains = [0, 1].map(|i| {
+			let speed = self.encoders[i].state.velocity.update(counts[i], now, pulses[i]);
+
+			// The axes stay where they were when the configuration menu opened.
+			if in_menu { 0 } else { self.options.acceleration_gain(speed) }
+		});
+		let knob_button_hold = self.options.knob_button_hold;
+		let knob_button_threshold = self.options.knob_button_threshold;
+		let relative_gamepad = self.axis_mode() == AxisMode::Relative;
 
 		self.report.x = parse_encoder(
-			rx_l,
+			counts[0],
 			&mut self.encoders[0].state,
-			ENC_PULSE,
+			pulses[0],
+			knob_sensitivity[0],
+			gains[0],
 			reverse.0,
 		);
 
 		self.report.y = parse_encoder(
-			rx_r,
+			counts[1],
 			&mut self.encoders[1].state,
-			ENC_PULSE,
+			pulses[1],
+			knob_sensitivity[1],
+			gains[1],
 			reverse.1,
 		);
+
+		for (i, encoder) in self.encoders.iter_mut().enumerate() {
+			let state = &mut encoder.state;
+
+			if (stalls >> i) & 1 == 1 {
+				state.fifo_stalls = state.fifo_stalls.saturating_add(1);
+			}
+
+			// The movement is handled by the configuration menu while it's open.
+			let delta = if in_menu { 0 } else { state.delta };
+			state.knob_button.update(delta, now, knob_button_threshold, knob_button_hold);
+
+			if delta != 0 {
+				state.last_movement = Some((state.delta.signum(), now));
+
+				// Movement is only accumulated when it will be reported as a relative value.
+				let scale = match routes[i] {
+					HidInterface::Mouse => Some(sensitivity[i] as i32),
+					HidInterface::Gamepad if relative_gamepad => Some(1),
+					_ => None,
+				};
+
+				if let Some(scale) = scale {
+					state.pending_movement.add(state.delta, scale, gains[i]);
+				}
+			}
+		}
 	}
 
 	/// Updates the HID report with the current state of the buttons.
@@ -217,7 +385,7 @@ impl SDVXController {
 				continue;
 			}
 
-			if is_pressed && state.last_pressed == false {
+			if is_pressed && !state.last_pressed {
 				state.last_debounce_time = Some(now);
 			}
 
@@ -251,24 +419,352 @@ impl SDVXController {
 		self.report.buttons = report;
 	}
 
-	// TODO: Add an "idle" lighting mode.
-	// TODO: Allow disabling lighting.
-	/// Handles the arcade buttons lighting system.
+	/// Opens the on-device configuration menu once START is held for [`MENU_HOLD_DURATION_US`],
+	/// and handles it while it's open. The options are saved to flash when the menu is closed.
+	///
+	/// While the menu is open the buttons and the encoders aren't reported to the host, the axes staying
+	/// where they were when it opened.
+	pub fn update_menu(&mut self) {
+		let now = self.timer.get_counter();
+
+		if let Some(menu) = self.menu.as_mut() {
+			// The menu works with the physical direction, so reversing the encoders doesn't flip it.
+			let reverse = self.options.reverse_encoders.state();
+			let deltas = [(&self.encoders[0], reverse.0), (&self.encoders[1], reverse.1)]
+				.map(|(encoder, reverse)| if reverse { -encoder.state.delta } else { encoder.state.delta });
+
+			if menu.update(&mut self.options, self.report.buttons, deltas) {
+				self.menu = None;
+				self.save_options();
+			}
+
+			return;
+		}
+
+		if self.report.buttons & 1 == 0 {
+			self.menu_hold_start = None;
+			return;
+		}
+
+		let hold_start = *self.menu_hold_start.get_or_insert(now);
+		let held = now.checked_duration_since(hold_start).unwrap_or(MicrosDurationU64::micros(0));
+
+		if held >= MicrosDurationU64::micros(MENU_HOLD_DURATION_US) {
+			self.menu = Some(ConfigMenu::new(now, self.report.buttons));
+			self.menu_hold_start = None;
+		}
+	}
+
+	/// Handles the arcade buttons lighting system, following the [`LightingMode`] set in the options.
+	///
+	/// While there's no input, the reactive modes play the idle animation instead
+	/// (see [`SDVXControllerOptions::with_idle_lighting`]).
 	pub fn update_lights(&mut self) {
+		let now = self.timer.get_counter();
+		let timeout = MicrosDurationU64::micros(HOST_LIGHTS_TIMEOUT_US);
+		let active = self.report.buttons != 0 || self.encoders.iter().any(|encoder| encoder.state.delta != 0);
+		let idle = self.idle.update(now, active, self.options.idle_timeout());
+
+		let idle_lamps = idle.map(|elapsed| match self.options.idle_animation.animation() {
+			Some(animation) => animation.lamps(elapsed),
+			None => 0,
+		});
+
+		let hold_duration = MicrosDurationU64::micros(KNOB_HOLD_DURATION_US);
+		let directions = [
+			self.encoders[0].state.direction(now, hold_duration),
+			self.encoders[1].state.direction(now, hold_duration),
+		];
+		let knob_lamps = self.options.knob_lighting.lamps(directions, now.duration_since_epoch());
+
+		let host_lamps = self.host_lamps
+			.filter(|(_, received)| now.checked_duration_since(*received).is_some_and(|elapsed| elapsed < timeout))
+			.map(|(lamps, _)| lamps);
+
+		let mode_lamps = self.options.lighting_mode.lamps(self.report.buttons, knob_lamps);
+
+		let lamps = match (&self.menu, self.options.lighting_mode) {
+			(Some(menu), _) => menu.lamps(&self.options, now),
+			(None, LightingMode::Off | LightingMode::AlwaysOn) => mode_lamps,
+			(None, LightingMode::Reactive | LightingMode::Inverted) => idle_lamps.unwrap_or(mode_lamps),
+			(None, LightingMode::HostDriven) => host_lamps.or(idle_lamps).unwrap_or(mode_lamps),
+		};
+
+		let fade_duration = self.options.lamp_fade_duration;
+
 		for (i, button) in self.buttons.iter_mut().enumerate() {
-			if (self.report.buttons >> i) & 1 == 1 {
-				button.turn_on();
-			}
-			else {
-				button.turn_off();
-			}
+			let brightness = self.options.lamp_brightness[i];
+
+			button.light(&self.pwm, (lamps >> i) & 1 == 1, brightness, fade_duration, now);
+		}
+	}
+
+	/// Draws the next frame of the RGB strip, following the [`RgbEffect`] set in the options.
+	///
+	/// Note: If the [`SDVXController::start_rgb`] method hasn't been called, this won't work.
+	pub fn update_rgb(&mut self) {
+		let Some(rgb) = self.rgb.as_mut() else { return; };
+
+		let now = self.timer.get_counter();
+		let frame_duration = MicrosDurationU64::micros(RGB_FRAME_DURATION_US);
+		let is_due = self.rgb_frame_time
+			.and_then(|frame_time| now.checked_duration_since(frame_time))
+			.is_none_or(|elapsed| elapsed >= frame_duration);
+
+		if rgb.is_ready() && is_due {
+			let hold_duration = MicrosDurationU64::micros(RGB_KNOB_HOLD_DURATION_US);
+			let directions = [
+				self.encoders[0].state.direction(now, hold_duration),
+				self.encoders[1].state.direction(now, hold_duration),
+			];
+			let colors = self.options.rgb_colors.map(Rgb::from_u32);
+			let mut frame = [Rgb::default(); RGB_LED_COUNT];
+
+			render_rgb(
+				self.options.rgb_effect,
+				colors,
+				directions,
+				self.report.buttons,
+				now.duration_since_epoch(),
+				&mut frame,
+			);
+
+			rgb.send(&frame);
+			self.rgb_frame_time = Some(now);
 		}
+
+		rgb.update();
+	}
+
+	/// Sets the lamps from an output report sent by the host, one bit per lamp following the
+	/// GPIO pin order.
+	pub fn set_host_lamps(&mut self, lamps: u8) {
+		self.host_lamps = Some((lamps, self.timer.get_counter()));
+	}
+
+	/// Returns the HID mode of the current session. A mode selected at boot (see [`BOOT_HID_MODES`])
+	/// takes precedence over the one set in the options.
+	///
+	/// Once the controller is started, the mode stays the same until the next boot.
+	pub fn hid_mode(&self) -> HidMode {
+		self.session_hid_mode
+			.or(self.boot_hid_mode)
+			.unwrap_or(self.options.hid_mode)
+	}
+
+	/// Returns the resolution of the gamepad's axes in the current session.
+	///
+	/// Once the controller is started, the resolution stays the same until the next boot.
+	pub fn axis_resolution(&self) -> AxisResolution {
+		self.session_axis_resolution.unwrap_or(self.options.axis_resolution)
+	}
+
+	/// Returns whether the gamepad's axes report the encoders' position or movement in the current session.
+	///
+	/// Once the controller is started, the mode stays the same until the next boot.
+	pub fn axis_mode(&self) -> AxisMode {
+		self.session_axis_mode.unwrap_or(self.options.axis_mode)
+	}
+
+	/// Returns the HID interface the given button reports to under the current HID mode.
+	pub fn button_route(&self, index: usize) -> HidInterface {
+		self.hid_mode().route(self.options.routing.buttons[index])
+	}
+
+	/// Returns the HID interface the given encoder reports to under the current HID mode.
+	pub fn encoder_route(&self, index: usize) -> HidInterface {
+		self.hid_mode().route(self.options.routing.encoders[index])
+	}
+
+	/// Returns the state of the buttons that report to the given HID interface.
+	fn routed_buttons(&self, interface: HidInterface) -> u8 {
+		if self.menu.is_some() { return 0; }
+
+		(0..BT_SIZE)
+			.filter(|i| self.button_route(*i) == interface)
+			.fold(0, |buttons, i| buttons | (self.report.buttons & (1 << i)))
+	}
+
+	/// Returns the virtual buttons of the encoders that report to the gamepad, two per encoder
+	/// following the encoder order. Empty unless enabled, see [`SDVXControllerOptions::with_knob_buttons`].
+	fn routed_knob_buttons(&self) -> u8 {
+		if self.menu.is_some() || !self.options.knob_buttons { return 0; }
+
+		(0..ENC_GPIO_SIZE)
+			.filter(|i| self.encoder_route(*i) == HidInterface::Gamepad)
+			.fold(0, |buttons, i| buttons | (self.encoders[i].state.knob_button.buttons() << (i * 2)))
+	}
+
+	/// Returns the pending movement of the encoder at the given index if it reports to the given
+	/// HID interface, `0` otherwise so it isn't sent (and confirmed) by two interfaces.
+	fn routed_movement(&self, index: usize, interface: HidInterface) -> i8 {
+		if self.encoder_route(index) != interface { return 0; }
+
+		self.encoders[index].state.pending_movement.report()
 	}
 
-	// TODO: Update the function to allow dynamic reporting based on the preferred HID mode.
 	/// Generates a new gamepad report based on the current state of the controller.
+	/// Only the inputs routed to [`HidInterface::Gamepad`] are included.
 	pub fn report_gamepad(&self) -> GamepadReport {
-		self.report.clone()
+		let axis = |i: usize, value: u8| {
+			if self.encoder_route(i) == HidInterface::Gamepad { value } else { 0 }
+		};
+
+		GamepadReport::new(
+			self.routed_buttons(HidInterface::Gamepad),
+			self.routed_knob_buttons(),
+			axis(0, self.report.x),
+			axis(1, self.report.y),
+		)
+	}
+
+	/// Generates a new high resolution gamepad report based on the current state of the controller,
+	/// for use with [`AxisResolution::High`]. Only the inputs routed to [`HidInterface::Gamepad`]
+	/// are included.
+	pub fn report_gamepad_wide(&self) -> GamepadWideReport {
+		let axis = |i: usize| {
+			if self.encoder_route(i) != HidInterface::Gamepad { return 0; }
+
+			encoder_position_wide(self.encoders[i].state.curr_value, self.options.encoder_pulses(i))
+		};
+
+		GamepadWideReport::new(
+			self.routed_buttons(HidInterface::Gamepad),
+			self.routed_knob_buttons(),
+			axis(0),
+			axis(1),
+		)
+	}
+
+	/// Generates a new relative gamepad report based on the current state of the controller,
+	/// for use with [`AxisMode::Relative`]. The encoders' movement (in counts) since the last
+	/// confirmed report is sent on the axes. Only the inputs routed to [`HidInterface::Gamepad`]
+	/// are included.
+	///
+	/// Note: Call [`SDVXController::confirm_relative_report`] once the report has been sent,
+	/// otherwise the same movement will be reported again.
+	pub fn report_gamepad_relative(&self) -> GamepadRelativeReport {
+		GamepadRelativeReport::new(
+			self.routed_buttons(HidInterface::Gamepad),
+			self.routed_knob_buttons(),
+			self.routed_movement(0, HidInterface::Gamepad),
+			self.routed_movement(1, HidInterface::Gamepad),
+		)
+	}
+
+	/// Removes the movement included in a relative gamepad report that was successfully sent
+	/// to the host. Any movement that didn't fit in the report is kept for the next one.
+	pub fn confirm_relative_report(&mut self, report: &GamepadRelativeReport) {
+		self.encoders[0].state.pending_movement.confirm(report.x);
+		self.encoders[1].state.pending_movement.confirm(report.y);
+	}
+
+	/// Generates a new keyboard report based on the current state of the controller.
+	/// The keys reported by each input are set by [`SDVXControllerOptions::with_key_map`].
+	/// The encoders' keys follow their virtual buttons, see [`SDVXControllerOptions::with_knob_buttons`].
+	/// Only the inputs routed to [`HidInterface::Keyboard`] are included.
+	pub fn report_keyboard(&self) -> KeyboardReport {
+		let key_map = &self.options.key_map;
+		let buttons = self.routed_buttons(HidInterface::Keyboard);
+		let mut report = KeyboardReport::default();
+
+		for (i, key) in key_map.buttons.iter().enumerate() {
+			if (buttons >> i) & 1 == 1 {
+				report.press(*key);
+			}
+		}
+
+		for (i, (encoder, keys)) in self.encoders.iter().zip(key_map.encoders.iter()).enumerate() {
+			if self.encoder_route(i) != HidInterface::Keyboard { continue; }
+
+			match encoder.state.knob_button.direction() {
+				-1 => report.press(keys.0),
+				1 => report.press(keys.1),
+				_ => (),
+			}
+		}
+
+		report
+	}
+
+	/// Generates a new mouse report based on the current state of the controller.
+	/// The encoders' movement since the last confirmed report is sent as relative X/Y motion.
+	/// Only the inputs routed to [`HidInterface::Mouse`] are included.
+	///
+	/// Note: Call [`SDVXController::confirm_mouse_report`] once the report has been sent,
+	/// otherwise the same movement will be reported again.
+	pub fn report_mouse(&self) -> MouseReport {
+		MouseReport::new(
+			self.routed_buttons(HidInterface::Mouse),
+			self.routed_movement(0, HidInterface::Mouse),
+			self.routed_movement(1, HidInterface::Mouse),
+		)
+	}
+
+	/// Removes the movement included in a mouse report that was successfully sent to the host.
+	/// Any movement that didn't fit in the report is kept for the next one.
+	pub fn confirm_mouse_report(&mut self, report: &MouseReport) {
+		self.encoders[0].state.pending_movement.confirm(report.x);
+		self.encoders[1].state.pending_movement.confirm(report.y);
+	}
+
+	/// Writes the controller's current options to flash, so they are restored on the next boot.
+	pub fn save_options(&self) {
+		storage::save(&self.options);
+	}
+
+	/// Handles a request of the configuration protocol, returning the response for the host.
+	///
+	/// Changes to the options are applied immediately, except for the ones that only take effect
+	/// on the next boot (see [`OptionField`]).
+	pub fn handle_config(&mut self, request: &ConfigMessage) -> ConfigMessage {
+		let field = OptionField::from_id(request.field);
+		let index = request.index as usize;
+
+		match (ConfigCommand::from_id(request.command), field) {
+			(Some(ConfigCommand::Version), _) => request.respond(ConfigStatus::Ok, CONFIG_PROTOCOL_VERSION),
+			(Some(ConfigCommand::Get), Some(field)) => match self.options.field(field, index) {
+				Some(value) => request.respond(ConfigStatus::Ok, value),
+				None => request.respond(ConfigStatus::InvalidIndex, 0),
+			},
+			(Some(ConfigCommand::Set), Some(field)) => {
+				if index >= field.count() {
+					request.respond(ConfigStatus::InvalidIndex, 0)
+				}
+				else if self.options.set_field(field, index, request.value) {
+					request.respond(ConfigStatus::Ok, request.value)
+				}
+				else {
+					request.respond(ConfigStatus::InvalidValue, 0)
+				}
+			}
+			(Some(ConfigCommand::Get | ConfigCommand::Set), None) => request.respond(ConfigStatus::UnknownField, 0),
+			(Some(ConfigCommand::Commit), _) => {
+				self.save_options();
+				request.respond(ConfigStatus::Ok, 0)
+			}
+			(Some(ConfigCommand::Reset), _) => {
+				self.options = SDVXControllerOptions::default();
+				request.respond(ConfigStatus::Ok, 0)
+			}
+			(Some(ConfigCommand::Diagnostic), _) => match DiagnosticField::from_id(request.field) {
+				Some(field) => match self.diagnostic(field, index) {
+					Some(value) => request.respond(ConfigStatus::Ok, value),
+					None => request.respond(ConfigStatus::InvalidIndex, 0),
+				},
+				None => request.respond(ConfigStatus::UnknownField, 0),
+			},
+			(None, _) => request.respond(ConfigStatus::UnknownCommand, 0),
+		}
+	}
+
+	/// Reads the value at the given index of a diagnostic counter.
+	///
+	/// Returns `None` if the index is out of range for the field.
+	pub fn diagnostic(&self, field: DiagnosticField, index: usize) -> Option<u32> {
+		match field {
+			DiagnosticField::EncoderFifoStalls => self.encoders.get(index).map(|encoder| encoder.state.fifo_stalls),
+		}
 	}
 
 	/// Retrieves the controller's current options. Options can be chained for easier modification.
@@ -292,20 +788,64 @@ impl SDVXController {
 }
 
 
+/// Halts the controller, blinking the onboard LED once for the left encoder or twice for
+/// the right one (the encoder at the given index) until the pinout is fixed.
+fn signal_encoder_pin_error(led: &mut impl OutputPin, timer: &mut hal::Timer, index: usize) -> ! {
+	loop {
+		for _ in 0..=index {
+			led.set_high().unwrap();
+			timer.delay_ms(ENC_PIN_ERROR_BLINK_MS);
+			led.set_low().unwrap();
+			timer.delay_ms(ENC_PIN_ERROR_BLINK_MS);
+		}
+
+		timer.delay_ms(ENC_PIN_ERROR_PAUSE_MS);
+	}
+}
+
+
 /// Provides various configurations as to how the controller will operate.
+#[derive(Clone, Copy, PartialEq)]
 pub struct SDVXControllerOptions {
-	debounce_encoders: bool,
+	encoder_filter: u32,
 	debounce_duration: MicrosDurationU64,
 	debounce_mode: DebounceMode,
 	reverse_encoders: ReverseMode,
+	hid_mode: HidMode,
+	axis_resolution: AxisResolution,
+	axis_mode: AxisMode,
+	key_map: KeyMap,
+	mouse_sensitivity: [u8; ENC_GPIO_SIZE],
+	encoder_ppr: [u16; ENC_GPIO_SIZE],
+	knob_sensitivity: [u16; ENC_GPIO_SIZE],
+	acceleration_curve: AccelerationCurve,
+	acceleration_factor: u16,
+	acceleration_table: [u16; ACCEL_TABLE_SIZE],
+	routing: Routing,
+	idle_animation: IdleAnimation,
+	idle_timeout: u32,
+	lighting_mode: LightingMode,
+	knob_lighting: KnobLighting,
+	knob_buttons: bool,
+	knob_button_hold: MicrosDurationU64,
+	knob_button_threshold: u8,
+	lamp_brightness: [u8; BT_SIZE],
+	lamp_fade_duration: MicrosDurationU64,
+	rgb_effect: RgbEffect,
+	rgb_colors: [u32; ENC_GPIO_SIZE],
 }
 
 impl SDVXControllerOptions {
-	/// Sets whether to debounce the encoders.
+	/// Sets the time between two samples of the encoder pins, filtering out shorter bounces.
+	/// The value must be in microseconds, `0` samples as fast as possible. Values above
+	/// [`ENC_MAX_FILTER_US`] are ignored. Takes effect on the next boot.
 	///
-	/// Default is `false`.
-	pub fn with_debounce_encoders(&mut self, debounce_encoders: bool) -> &mut Self {
-		self.debounce_encoders = debounce_encoders;
+	/// Noisy encoders (e.g. optical ones) need a longer filter than clean mechanical ones,
+	/// at the cost of dropping pulses when spun faster than the filter can sample.
+	///
+	/// Default is `0`.
+	pub fn with_encoder_filter(&mut self, encoder_filter_us: u32) -> &mut Self {
+		if encoder_filter_us <= ENC_MAX_FILTER_US { self.encoder_filter = encoder_filter_us; }
 		self
 	}
 
@@ -334,8 +874,180 @@ impl SDVXControllerOptions {
 		self
 	}
 
-	pub fn debounce_encoders(&self) -> bool {
-		self.debounce_encoders
+	/// Sets the resolution of the gamepad's axes. Takes effect on the next boot.
+	///
+	/// Default is [`AxisResolution::Standard`].
+	pub fn with_axis_resolution(&mut self, axis_resolution: AxisResolution) -> &mut Self {
+		self.axis_resolution = axis_resolution;
+		self
+	}
+
+	/// Sets whether the gamepad's axes report the encoders' position or their movement.
+	/// Relative axes always report 8-bit values, regardless of the axis resolution.
+	/// Takes effect on the next boot.
+	///
+	/// Default is [`AxisMode::Absolute`].
+	pub fn with_axis_mode(&mut self, axis_mode: AxisMode) -> &mut Self {
+		self.axis_mode = axis_mode;
+		self
+	}
+
+	/// Sets the type of HID device the controller reports as.
+	/// Can be overridden at boot, see [`BOOT_HID_MODES`].
+	///
+	/// Default is [`HidMode::Gamepad`].
+	pub fn with_hid_mode(&mut self, hid_mode: HidMode) -> &mut Self {
+		self.hid_mode = hid_mode;
+		self
+	}
+
+	/// Sets the keys reported by each input when using [`HidMode::Keyboard`].
+	///
+	/// Default is [`KeyMap::default`].
+	pub fn with_key_map(&mut self, key_map: KeyMap) -> &mut Self {
+		self.key_map = key_map;
+		self
+	}
+
+	/// Sets the multiplier applied to the movement of the left (X axis) and right (Y axis)
+	/// encoders when using [`HidMode::Mouse`].
+	///
+	/// Default is [`MOUSE_DEFAULT_SENSITIVITY`] for both axes.
+	pub fn with_mouse_sensitivity(&mut self, x: u8, y: u8) -> &mut Self {
+		self.mouse_sensitivity = [x, y];
+		self
+	}
+
+	/// Sets the resolution (pulses per revolution) of the left and right encoders.
+	/// A value of `0` is ignored.
+	///
+	/// Default is [`ENC_DEFAULT_PPR`] for both encoders.
+	pub fn with_encoder_ppr(&mut self, left: u16, right: u16) -> &mut Self {
+		for (ppr, value) in self.encoder_ppr.iter_mut().zip([left, right]) {
+			if value > 0 { *ppr = value; }
+		}
+		self
+	}
+
+	/// Sets how many times a full revolution of the left and right encoders sweeps their axis,
+	/// in percent (e.g. `50` takes two revolutions per sweep). Values outside of
+	/// `1..=ENC_MAX_SENSITIVITY` are ignored.
+	///
+	/// Default is [`ENC_DEFAULT_SENSITIVITY`] for both encoders.
+	pub fn with_knob_sensitivity(&mut self, left: u16, right: u16) -> &mut Self {
+		for (sensitivity, value) in self.knob_sensitivity.iter_mut().zip([left, right]) {
+			if (1..=ENC_MAX_SENSITIVITY).contains(&value) { *sensitivity = value; }
+		}
+		self
+	}
+
+	/// Sets the curve scaling the encoders' movement by their speed, and the gain (in percent)
+	/// the linear and quadratic curves add at one revolution per second.
+	///
+	/// Default is [`AccelerationCurve::None`], with a factor of [`ACCEL_DEFAULT_FACTOR`].
+	pub fn with_acceleration(&mut self, curve: AccelerationCurve, factor: u16) -> &mut Self {
+		self.acceleration_curve = curve;
+		self.acceleration_factor = factor;
+		self
+	}
+
+	/// Sets the gains (in percent) of [`AccelerationCurve::Custom`], one every [`ACCEL_TABLE_STEP`]
+	/// starting at rest. Values outside of `1..=ACCEL_MAX_GAIN` are ignored.
+	///
+	/// Default is [`ACCEL_DEFAULT_TABLE`].
+	pub fn with_acceleration_table(&mut self, table: [u16; ACCEL_TABLE_SIZE]) -> &mut Self {
+		for (gain, value) in self.acceleration_table.iter_mut().zip(table) {
+			if (1..=ACCEL_MAX_GAIN).contains(&value) { *gain = value; }
+		}
+		self
+	}
+
+	/// Sets the HID interface each input reports to when using [`HidMode::Composite`].
+	///
+	/// Default is [`Routing::default`].
+	pub fn with_routing(&mut self, routing: Routing) -> &mut Self {
+		self.routing = routing;
+		self
+	}
+
+	/// Sets the animation played by the lamps after the given time (in seconds) without any input.
+	/// The lamps go back to normal on the next input.
+	///
+	/// Default is [`IdleAnimation::None`], with a timeout of [`IDLE_DEFAULT_TIMEOUT_S`].
+	pub fn with_idle_lighting(&mut self, idle_animation: IdleAnimation, idle_timeout_s: u32) -> &mut Self {
+		self.idle_animation = idle_animation;
+		self.idle_timeout = idle_timeout_s;
+		self
+	}
+
+	/// Sets what the lamps show.
+	///
+	/// Default is [`LightingMode::HostDriven`].
+	pub fn with_lighting_mode(&mut self, lighting_mode: LightingMode) -> &mut Self {
+		self.lighting_mode = lighting_mode;
+		self
+	}
+
+	/// Sets how the lamps react to the encoders spinning.
+	/// The lamps controlled by the host aren't affected.
+	///
+	/// Default is [`KnobLighting::None`].
+	pub fn with_knob_lighting(&mut self, knob_lighting: KnobLighting) -> &mut Self {
+		self.knob_lighting = knob_lighting;
+		self
+	}
+
+	/// Sets whether the encoders press virtual gamepad buttons while spinning, one per direction.
+	/// A button is pressed once its encoder travels `threshold` counts in one direction and stays
+	/// held until the encoder rests for `hold_us` microseconds. See [`KnobButton`].
+	///
+	/// The hold time and threshold also apply to the encoders' keys in [`HidMode::Keyboard`],
+	/// which are always pressed regardless of `enabled`.
+	///
+	/// Default is disabled, held for [`KNOB_BUTTON_DEFAULT_HOLD_US`]
+	/// after [`KNOB_BUTTON_DEFAULT_THRESHOLD`] counts.
+	pub fn with_knob_buttons(&mut self, enabled: bool, hold_us: u64, threshold: u8) -> &mut Self {
+		self.knob_buttons = enabled;
+		self.knob_button_hold = MicrosDurationU64::micros(hold_us);
+		self.knob_button_threshold = threshold;
+		self
+	}
+
+	/// Sets the brightness of each lamp, following the GPIO pin order.
+	///
+	/// Default is [`LAMP_DEFAULT_BRIGHTNESS`] for every lamp.
+	pub fn with_lamp_brightness(&mut self, lamp_brightness: [u8; BT_SIZE]) -> &mut Self {
+		self.lamp_brightness = lamp_brightness;
+		self
+	}
+
+	/// Sets the time the lamps take to fade out once turned off.
+	/// The value must be in microseconds.
+	///
+	/// Default is [`LAMP_DEFAULT_FADE_DURATION_US`].
+	pub fn with_lamp_fade_duration(&mut self, lamp_fade_duration_us: u64) -> &mut Self {
+		self.lamp_fade_duration = MicrosDurationU64::micros(lamp_fade_duration_us);
+		self
+	}
+
+	/// Sets how the RGB strip reacts to the controller.
+	///
+	/// Default is [`RgbEffect::Reactive`].
+	pub fn with_rgb_effect(&mut self, rgb_effect: RgbEffect) -> &mut Self {
+		self.rgb_effect = rgb_effect;
+		self
+	}
+
+	/// Sets the color (0xRRGGBB) of the RGB strip's sections of the left and right encoders.
+	///
+	/// Default is [`RGB_DEFAULT_COLORS`].
+	pub fn with_rgb_colors(&mut self, left: u32, right: u32) -> &mut Self {
+		self.rgb_colors = [left & 0xFFFFFF, right & 0xFFFFFF];
+		self
+	}
+
+	pub fn encoder_filter(&self) -> MicrosDurationU32 {
+		MicrosDurationU32::micros(self.encoder_filter)
 	}
 
 	pub fn debounce_duration(&self) -> MicrosDurationU64 {
@@ -349,15 +1061,307 @@ impl SDVXControllerOptions {
 	pub fn reverse_encoders(&self) -> ReverseMode {
 		self.reverse_encoders
 	}
+
+	pub fn hid_mode(&self) -> HidMode {
+		self.hid_mode
+	}
+
+	pub fn axis_resolution(&self) -> AxisResolution {
+		self.axis_resolution
+	}
+
+	pub fn axis_mode(&self) -> AxisMode {
+		self.axis_mode
+	}
+
+	pub fn key_map(&self) -> KeyMap {
+		self.key_map
+	}
+
+	pub fn mouse_sensitivity(&self) -> (u8, u8) {
+		(self.mouse_sensitivity[0], self.mouse_sensitivity[1])
+	}
+
+	pub fn encoder_ppr(&self) -> (u16, u16) {
+		(self.encoder_ppr[0], self.encoder_ppr[1])
+	}
+
+	pub fn knob_sensitivity(&self) -> (u16, u16) {
+		(self.knob_sensitivity[0], self.knob_sensitivity[1])
+	}
+
+	/// Returns the number of counts needed for the encoder at the given index to complete
+	/// a full revolution. Alias the number of reports per revolution.
+	pub fn encoder_pulses(&self, index: usize) -> i32 {
+		self.encoder_ppr[index] as i32 * ENC_COUNTS_PER_PULSE
+	}
+
+	pub fn acceleration_curve(&self) -> AccelerationCurve {
+		self.acceleration_curve
+	}
+
+	pub fn acceleration_factor(&self) -> u16 {
+		self.acceleration_factor
+	}
+
+	pub fn acceleration_table(&self) -> [u16; ACCEL_TABLE_SIZE] {
+		self.acceleration_table
+	}
+
+	/// Returns the gain to apply to the movement of an encoder spinning at the given speed,
+	/// in hundredths of a revolution per second. See [`AccelerationCurve::gain`].
+	pub fn acceleration_gain(&self, speed: u32) -> u16 {
+		self.acceleration_curve.gain(speed, self.acceleration_factor, &self.acceleration_table)
+	}
+
+	pub fn routing(&self) -> Routing {
+		self.routing
+	}
+
+	pub fn idle_animation(&self) -> IdleAnimation {
+		self.idle_animation
+	}
+
+	pub fn idle_timeout(&self) -> MicrosDurationU64 {
+		MicrosDurationU64::secs(self.idle_timeout as u64)
+	}
+
+	pub fn lighting_mode(&self) -> LightingMode {
+		self.lighting_mode
+	}
+
+	pub fn knob_lighting(&self) -> KnobLighting {
+		self.knob_lighting
+	}
+
+	pub fn knob_buttons(&self) -> bool {
+		self.knob_buttons
+	}
+
+	pub fn knob_button_hold(&self) -> MicrosDurationU64 {
+		self.knob_button_hold
+	}
+
+	pub fn knob_button_threshold(&self) -> u8 {
+		self.knob_button_threshold
+	}
+
+	pub fn lamp_brightness(&self) -> [u8; BT_SIZE] {
+		self.lamp_brightness
+	}
+
+	pub fn lamp_fade_duration(&self) -> MicrosDurationU64 {
+		self.lamp_fade_duration
+	}
+
+	pub fn rgb_effect(&self) -> RgbEffect {
+		self.rgb_effect
+	}
+
+	pub fn rgb_colors(&self) -> (u32, u32) {
+		(self.rgb_colors[0], self.rgb_colors[1])
+	}
+
+	/// Reads the value at the given index of an option as a raw number.
+	///
+	/// Returns `None` if the index is out of range for the option.
+	pub fn field(&self, field: OptionField, index: usize) -> Option<u32> {
+		if index >= field.count() { return None; }
+
+		let value = match field {
+			OptionField::EncoderFilter => self.encoder_filter,
+			OptionField::DebounceDuration => self.debounce_duration.to_micros().min(u32::MAX as u64) as u32,
+			OptionField::DebounceMode => self.debounce_mode as u32,
+			OptionField::ReverseEncoders => self.reverse_encoders as u32,
+			OptionField::HidMode => self.hid_mode as u32,
+			OptionField::AxisResolution => self.axis_resolution as u32,
+			OptionField::AxisMode => self.axis_mode as u32,
+			OptionField::KeyMapButtons => self.key_map.buttons[index] as u32,
+			OptionField::KeyMapEncoders => {
+				let keys = self.key_map.encoders[index / 2];
+				if index.is_multiple_of(2) { keys.0 as u32 } else { keys.1 as u32 }
+			}
+			OptionField::MouseSensitivity => self.mouse_sensitivity[index] as u32,
+			OptionField::EncoderPpr => self.encoder_ppr[index] as u32,
+			OptionField::KnobSensitivity => self.knob_sensitivity[index] as u32,
+			OptionField::AccelerationCurve => self.acceleration_curve as u32,
+			OptionField::AccelerationFactor => self.acceleration_factor as u32,
+			OptionField::AccelerationTable => self.acceleration_table[index] as u32,
+			OptionField::RoutingButtons => self.routing.buttons[index] as u32,
+			OptionField::RoutingEncoders => self.routing.encoders[index] as u32,
+			OptionField::IdleAnimation => self.idle_animation as u32,
+			OptionField::IdleTimeout => self.idle_timeout,
+			OptionField::LightingMode => self.lighting_mode as u32,
+			OptionField::LampBrightness => self.lamp_brightness[index] as u32,
+			OptionField::LampFadeDuration => self.lamp_fade_duration.to_micros().min(u32::MAX as u64) as u32,
+			OptionField::RgbEffect => self.rgb_effect as u32,
+			OptionField::RgbColors => self.rgb_colors[index],
+			OptionField::KnobLighting => self.knob_lighting as u32,
+			OptionField::KnobButtons => self.knob_buttons as u32,
+			OptionField::KnobButtonHold => self.knob_button_hold.to_micros().min(u32::MAX as u64) as u32,
+			OptionField::KnobButtonThreshold => self.knob_button_threshold as u32,
+		};
+
+		Some(value)
+	}
+
+	/// Writes a raw number to the value at the given index of an option.
+	///
+	/// Returns `false` and leaves the option untouched if the index or the value are invalid.
+	pub fn set_field(&mut self, field: OptionField, index: usize, value: u32) -> bool {
+		if index >= field.count() { return false; }
+
+		let byte = u8::try_from(value).ok();
+
+		match field {
+			OptionField::EncoderFilter => match value {
+				0..=ENC_MAX_FILTER_US => self.encoder_filter = value,
+				_ => return false,
+			},
+			OptionField::DebounceDuration => {
+				self.debounce_duration = MicrosDurationU64::micros(value as u64);
+			}
+			OptionField::DebounceMode => match DebounceMode::ALL.get(value as usize) {
+				Some(debounce_mode) => self.debounce_mode = *debounce_mode,
+				None => return false,
+			},
+			OptionField::ReverseEncoders => match ReverseMode::ALL.get(value as usize) {
+				Some(reverse_encoders) => self.reverse_encoders = *reverse_encoders,
+				None => return false,
+			},
+			OptionField::AxisResolution => match AxisResolution::ALL.get(value as usize) {
+				Some(axis_resolution) => self.axis_resolution = *axis_resolution,
+				None => return false,
+			},
+			OptionField::AxisMode => match AxisMode::ALL.get(value as usize) {
+				Some(axis_mode) => self.axis_mode = *axis_mode,
+				None => return false,
+			},
+			OptionField::HidMode => match HidMode::ALL.get(value as usize) {
+				Some(hid_mode) => self.hid_mode = *hid_mode,
+				None => return false,
+			},
+			OptionField::KeyMapButtons => match byte {
+				Some(key) => self.key_map.buttons[index] = key,
+				None => return false,
+			},
+			OptionField::KeyMapEncoders => match byte {
+				Some(key) if index.is_multiple_of(2) => self.key_map.encoders[index / 2].0 = key,
+				Some(key) => self.key_map.encoders[index / 2].1 = key,
+				None => return false,
+			},
+			OptionField::MouseSensitivity => match byte {
+				Some(sensitivity) => self.mouse_sensitivity[index] = sensitivity,
+				None => return false,
+			},
+			OptionField::RoutingButtons => match HidInterface::ALL.get(value as usize) {
+				Some(interface) => self.routing.buttons[index] = *interface,
+				None => return false,
+			},
+			OptionField::RoutingEncoders => match HidInterface::ALL.get(value as usize) {
+				Some(interface) => self.routing.encoders[index] = *interface,
+				None => return false,
+			},
+			OptionField::IdleAnimation => match IdleAnimation::ALL.get(value as usize) {
+				Some(idle_animation) => self.idle_animation = *idle_animation,
+				None => return false,
+			},
+			OptionField::IdleTimeout => {
+				self.idle_timeout = value;
+			}
+			OptionField::LightingMode => match LightingMode::ALL.get(value as usize) {
+				Some(lighting_mode) => self.lighting_mode = *lighting_mode,
+				None => return false,
+			},
+			OptionField::LampBrightness => match byte {
+				Some(brightness) => self.lamp_brightness[index] = brightness,
+				None => return false,
+			},
+			OptionField::LampFadeDuration => {
+				self.lamp_fade_duration = MicrosDurationU64::micros(value as u64);
+			}
+			OptionField::RgbEffect => match RgbEffect::ALL.get(value as usize) {
+				Some(rgb_effect) => self.rgb_effect = *rgb_effect,
+				None => return false,
+			},
+			OptionField::RgbColors => match value {
+				0..=0xFFFFFF => self.rgb_colors[index] = value,
+				_ => return false,
+			},
+			OptionField::EncoderPpr => match u16::try_from(value) {
+				Ok(ppr) if ppr > 0 => self.encoder_ppr[index] = ppr,
+				_ => return false,
+			},
+			OptionField::KnobSensitivity => match u16::try_from(value) {
+				Ok(sensitivity) if (1..=ENC_MAX_SENSITIVITY).contains(&sensitivity) => {
+					self.knob_sensitivity[index] = sensitivity;
+				}
+				_ => return false,
+			},
+			OptionField::KnobLighting => match KnobLighting::ALL.get(value as usize) {
+				Some(knob_lighting) => self.knob_lighting = *knob_lighting,
+				None => return false,
+			},
+			OptionField::KnobButtons => match value {
+				0 | 1 => self.knob_buttons = value == 1,
+				_ => return false,
+			},
+			OptionField::KnobButtonHold => {
+				self.knob_button_hold = MicrosDurationU64::micros(value as u64);
+			}
+			OptionField::KnobButtonThreshold => match byte {
+				Some(threshold) => self.knob_button_threshold = threshold,
+				None => return false,
+			},
+			OptionField::AccelerationCurve => match AccelerationCurve::ALL.get(value as usize) {
+				Some(acceleration_curve) => self.acceleration_curve = *acceleration_curve,
+				None => return false,
+			},
+			OptionField::AccelerationFactor => match u16::try_from(value) {
+				Ok(factor) => self.acceleration_factor = factor,
+				_ => return false,
+			},
+			OptionField::AccelerationTable => match u16::try_from(value) {
+				Ok(gain) if (1..=ACCEL_MAX_GAIN).contains(&gain) => {
+					self.acceleration_table[index] = gain;
+				}
+				_ => return false,
+			},
+		}
+
+		true
+	}
 }
 
 impl Default for SDVXControllerOptions {
 	fn default() -> Self {
 		Self {
-			debounce_encoders: false,
+			encoder_filter: 0,
 			debounce_duration: MicrosDurationU64::micros(SW_DEFAULT_DEBOUNCE_DURATION_US),
 			debounce_mode: DebounceMode::default(),
 			reverse_encoders: ReverseMode::default(),
+			hid_mode: HidMode::default(),
+			axis_resolution: AxisResolution::default(),
+			axis_mode: AxisMode::default(),
+			key_map: KeyMap::default(),
+			mouse_sensitivity: [MOUSE_DEFAULT_SENSITIVITY; ENC_GPIO_SIZE],
+			encoder_ppr: [ENC_DEFAULT_PPR; ENC_GPIO_SIZE],
+			knob_sensitivity: [ENC_DEFAULT_SENSITIVITY; ENC_GPIO_SIZE],
+			acceleration_curve: AccelerationCurve::default(),
+			acceleration_factor: ACCEL_DEFAULT_FACTOR,
+			acceleration_table: ACCEL_DEFAULT_TABLE,
+			routing: Routing::default(),
+			idle_animation: IdleAnimation::default(),
+			idle_timeout: IDLE_DEFAULT_TIMEOUT_S,
+			lighting_mode: LightingMode::default(),
+			knob_lighting: KnobLighting::default(),
+			knob_buttons: false,
+			knob_button_hold: MicrosDurationU64::micros(KNOB_BUTTON_DEFAULT_HOLD_US as u64),
+			knob_button_threshold: KNOB_BUTTON_DEFAULT_THRESHOLD,
+			lamp_brightness: [LAMP_DEFAULT_BRIGHTNESS; BT_SIZE],
+			lamp_fade_duration: MicrosDurationU64::micros(LAMP_DEFAULT_FADE_DURATION_US),
+			rgb_effect: RgbEffect::default(),
+			rgb_colors: RGB_DEFAULT_COLORS,
 		}
 	}
 }
@@ -370,17 +1374,22 @@ pub enum DebounceMode {
 	/// Disables debouncing.
 	#[default] None,
 	/// Immediately reports when a switch is triggered and holds it for an N amount of time.
-	///	Also known as "eager debouncing".
+	/// Also known as "eager debouncing".
 	Hold,
 	/// Waits for a switch to output a constant N amount of time before reporting.
 	/// Also known as "deferred debouncing".
 	Wait,
 }
 
+impl DebounceMode {
+	/// Every debounce mode, ordered by their serialized value.
+	pub const ALL: [DebounceMode; 3] = [DebounceMode::None, DebounceMode::Hold, DebounceMode::Wait];
+}
+
 
 /// Determines which encoders should reverse their direction when reporting their data.
 /// Default is [`ReverseMode::None`].
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, PartialEq)]
 pub enum ReverseMode {
 	/// Keeps the encoders' direction as reported.
 	#[default] None,
@@ -393,6 +1402,9 @@ pub enum ReverseMode {
 }
 
 impl ReverseMode {
+	/// Every reverse mode, ordered by their serialized value.
+	pub const ALL: [ReverseMode; 4] = [ReverseMode::None, ReverseMode::Both, ReverseMode::Left, ReverseMode::Right];
+
 	/// Returns the configuration of the encoders in a boolean tuple.
 	/// The first item is the left encoder's configuration, while the second item is the right one.
 	pub fn state(&self) -> (bool, bool) {
@@ -406,6 +1418,148 @@ impl ReverseMode {
 }
 
 
+/// Determines the type of HID device the controller reports as.
+/// Default is [`HidMode::Gamepad`].
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum HidMode {
+	/// Reports the buttons and encoders as a game controller.
+	#[default] Gamepad,
+	/// Reports the buttons and the encoders' direction as key presses.
+	Keyboard,
+	/// Reports the buttons as mouse buttons and the encoders' movement as relative X/Y motion.
+	Mouse,
+	/// Exposes a gamepad, a keyboard and a mouse interface at the same time.
+	/// Each input reports to the interface set by [`SDVXControllerOptions::with_routing`].
+	Composite,
+}
+
+impl HidMode {
+	/// Every HID mode, ordered by their serialized value.
+	pub const ALL: [HidMode; 4] = [HidMode::Gamepad, HidMode::Keyboard, HidMode::Mouse, HidMode::Composite];
+
+	/// Reports whether the given HID interface is exposed to the host in this mode.
+	pub fn uses(&self, interface: HidInterface) -> bool {
+		match self {
+			HidMode::Composite => true,
+			_ => self.route(interface) == interface,
+		}
+	}
+
+	/// Returns the HID interface an input with the given route reports to in this mode.
+	/// Routes are only followed in [`HidMode::Composite`], every other mode has a single interface.
+	pub fn route(&self, route: HidInterface) -> HidInterface {
+		match self {
+			HidMode::Gamepad => HidInterface::Gamepad,
+			HidMode::Keyboard => HidInterface::Keyboard,
+			HidMode::Mouse => HidInterface::Mouse,
+			HidMode::Composite => route,
+		}
+	}
+}
+
+
+/// Determines the resolution of the gamepad's axes, in every HID mode exposing the gamepad.
+/// Default is [`AxisResolution::Standard`].
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum AxisResolution {
+	/// Reports 8-bit axes through [`GamepadReport`].
+	#[default] Standard,
+	/// Reports 16-bit axes through [`GamepadWideReport`].
+	High,
+}
+
+impl AxisResolution {
+	/// Every axis resolution, ordered by their serialized value.
+	pub const ALL: [AxisResolution; 2] = [AxisResolution::Standard, AxisResolution::High];
+}
+
+
+/// Determines whether the gamepad's axes report the encoders' position or their movement.
+/// Default is [`AxisMode::Absolute`].
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum AxisMode {
+	/// Reports the encoders' position, wrapping around once per sweep.
+	#[default] Absolute,
+	/// Reports the encoders' movement since the last report through [`GamepadRelativeReport`].
+	/// Nothing is lost if the host misses reports, the movement is sent once it catches up.
+	Relative,
+}
+
+impl AxisMode {
+	/// Every axis mode, ordered by their serialized value.
+	pub const ALL: [AxisMode; 2] = [AxisMode::Absolute, AxisMode::Relative];
+}
+
+
+/// Represents one of the HID interfaces the controller can expose to the host.
+#[derive(Clone, Copy, PartialEq)]
+pub enum HidInterface {
+	/// Reports through [`GamepadReport`].
+	Gamepad,
+	/// Reports through [`KeyboardReport`].
+	Keyboard,
+	/// Reports through [`MouseReport`].
+	Mouse,
+}
+
+impl HidInterface {
+	/// Every HID interface, ordered by their serialized value.
+	pub const ALL: [HidInterface; 3] = [HidInterface::Gamepad, HidInterface::Keyboard, HidInterface::Mouse];
+}
+
+
+/// Determines which HID interface each input of the controller reports to in [`HidMode::Composite`].
+#[derive(Clone, Copy, PartialEq)]
+pub struct Routing {
+	/// The interfaces of the buttons, following the GPIO pin order.
+	pub buttons: [HidInterface; BT_SIZE],
+	/// The interfaces of the encoders, following the GPIO pin order.
+	pub encoders: [HidInterface; ENC_GPIO_SIZE],
+}
+
+impl Default for Routing {
+	/// Every input reports to the gamepad interface.
+	fn default() -> Self {
+		Self {
+			buttons: [HidInterface::Gamepad; BT_SIZE],
+			encoders: [HidInterface::Gamepad; ENC_GPIO_SIZE],
+		}
+	}
+}
+
+
+/// Determines which key is reported by each input of the controller in [`HidMode::Keyboard`].
+/// Key codes are HID keyboard usages, see [`KeyboardUsage`].
+#[derive(Clone, Copy, PartialEq)]
+pub struct KeyMap {
+	/// The key codes of the buttons, following the GPIO pin order.
+	pub buttons: [u8; BT_SIZE],
+	/// The key codes of the encoders as a (counter-clockwise, clockwise) pair.
+	pub encoders: [(u8, u8); ENC_GPIO_SIZE],
+}
+
+impl Default for KeyMap {
+	/// The default key bindings used by most PC simulators (USC, K-Shoot Mania).
+	fn default() -> Self {
+		Self {
+			buttons: [
+				KeyboardUsage::KeyboardEnter as u8,	// START
+				KeyboardUsage::KeyboardDd as u8,	// BT-A
+				KeyboardUsage::KeyboardFf as u8,	// BT-B
+				KeyboardUsage::KeyboardJj as u8,	// BT-C
+				KeyboardUsage::KeyboardKk as u8,	// BT-D
+				KeyboardUsage::KeyboardCc as u8,	// FX-L
+				KeyboardUsage::KeyboardMm as u8,	// FX-R
+			],
+			encoders: [
+				(KeyboardUsage::KeyboardWw as u8, KeyboardUsage::KeyboardEe as u8),	// VOL-L
+				(KeyboardUsage::KeyboardOo as u8, KeyboardUsage::KeyboardPp as u8),	// VOL-R
+			],
+		}
+	}
+}
+
+
 /// Represents an encoder (knob) on the controller.
 pub struct Encoder {
 	pin_a: DynPio0Pin,
@@ -427,6 +1581,11 @@ impl Encoder {
 	pub fn pins(&self) -> (&DynPio0Pin, &DynPio0Pin) {
 		(&self.pin_a, &self.pin_b)
 	}
+
+	/// Returns whether pin B is the GPIO right after pin A, as required by the encoder program.
+	pub fn has_adjacent_pins(&self) -> bool {
+		encoder_pins_adjacent(self.pin_a.id().num, self.pin_b.id().num)
+	}
 }
 
 
@@ -434,21 +1593,50 @@ impl Encoder {
 pub struct EncoderState {
 	/// The previous value reported by the encoder.
 	pub prev_value: u32,
-	/// The current delta reported by the encoder.
+	/// The travel within the current axis sweep, in hundredths of a count.
 	pub curr_value: i32,
+	/// The change in value registered by the last update.
+	pub delta: i32,
+	/// The direction (`-1` or `1`) and time of the last registered movement.
+	pub last_movement: Option<(i32, hal::timer::Instant)>,
+	/// The movement that hasn't been reported to the host as a relative value yet.
+	pub pending_movement: PendingMovement,
+	/// The updates during which the encoder's state machine stalled on a full RX FIFO, dropping pulses.
+	/// A lower bound of the stalls, see [`take_encoder_stalls`].
+	pub fifo_stalls: u32,
+	/// The virtual buttons pressed by the encoder spinning.
+	pub knob_button: KnobButton,
+	/// The speed of the encoder, driving the acceleration curve.
+	pub velocity: KnobVelocity,
+}
+
+impl EncoderState {
+	/// Returns the direction the encoder is spinning in (`-1` or `1`), or `0` if it hasn't moved
+	/// within the given hold duration.
+	pub fn direction(&self, now: hal::timer::Instant, hold_duration: MicrosDurationU64) -> i32 {
+		match self.last_movement {
+			Some((direction, time)) => {
+				let elapsed = now.checked_duration_since(time)
+					.unwrap_or(MicrosDurationU64::micros(0));
+
+				if elapsed <= hold_duration { direction } else { 0 }
+			}
+			None => 0,
+		}
+	}
 }
 
 
 /// Represents a button on the controller.
 pub struct Button {
 	sw_pin: DynInputPin,
-	led_pin: DynOutputPin,
+	led_pin: DynPwmPin,
 	state: ButtonState,
 }
 
 impl Button {
 	/// Associates a new button.
-	pub fn new(sw_pin: DynInputPin, led_pin: DynOutputPin) -> Self {
+	pub fn new(sw_pin: DynInputPin, led_pin: DynPwmPin) -> Self {
 		Self {
 			led_pin,
 			sw_pin,
@@ -463,18 +1651,47 @@ impl Button {
 			.unwrap_or(false)
 	}
 
-	/// Turns this button's LED on.
-	pub fn turn_on(&mut self) {
-		self.led_pin
-			.set_high()
-			.unwrap();
+	/// Sets the brightness of this button's LED through its PWM channel, see [`pwm_level`].
+	pub fn set_brightness(&mut self, pwm: &pac::PWM, brightness: u8) {
+		let id = self.led_pin.id().num;
+		let level = pwm_level(brightness);
+
+		// Even pins are wired to the channel A of their slice, odd pins to the channel B.
+		pwm.ch(pwm_slice(id)).cc().modify(|_, w| unsafe {
+			if id.is_multiple_of(2) { w.a().bits(level) } else { w.b().bits(level) }
+		});
 	}
 
-	/// Turns this button's LED off.
-	pub fn turn_off(&mut self) {
-		self.led_pin
-			.set_low()
-			.unwrap();
+	/// Turns this button's LED on at the given brightness, or fades it out over the given duration
+	/// once it's turned off (see [`fade_out`]).
+	pub fn light(
+		&mut self,
+		pwm: &pac::PWM,
+		lit: bool,
+		brightness: u8,
+		fade_duration: MicrosDurationU64,
+		now: hal::timer::Instant,
+	) {
+		let state = &mut self.state;
+
+		if state.lamp_lit && !lit {
+			state.lamp_released_at = Some(now);
+		}
+
+		state.lamp_lit = lit;
+
+		let brightness = match (lit, state.lamp_released_at) {
+			(true, _) => brightness,
+			(false, Some(released_at)) => {
+				let elapsed = now.checked_duration_since(released_at)
+					.unwrap_or(MicrosDurationU64::micros(0));
+
+				fade_out(brightness, elapsed, fade_duration)
+			}
+			(false, None) => 0,
+		};
+
+		self.set_brightness(pwm, brightness);
 	}
 }
 
@@ -484,6 +1701,8 @@ impl Button {
 pub struct ButtonState {
 	last_debounce_time: Option<hal::timer::Instant>,
 	last_pressed: bool,
+	lamp_lit: bool,
+	lamp_released_at: Option<hal::timer::Instant>,
 }
 
 
@@ -491,5 +1710,9 @@ pub struct ButtonState {
 pub type DynInputPin = gpio::Pin<gpio::DynPinId, gpio::FunctionSioInput, gpio::PullUp>;
 /// Type alias for a non-ID pin with a pull-down output configuration.
 pub type DynOutputPin = gpio::Pin<gpio::DynPinId, gpio::FunctionSioOutput, gpio::PullDown>;
+/// Type alias for a non-ID pin driven by its PWM slice.
+pub type DynPwmPin = gpio::Pin<gpio::DynPinId, gpio::FunctionPwm, gpio::PullDown>;
 /// Type alias for a non-ID pin for use with the PIO0.
 pub type DynPio0Pin = gpio::Pin<gpio::DynPinId, gpio::FunctionPio0, gpio::PullUp>;
+/// Type alias for a non-ID pin for use with the PIO1.
+pub type DynPio1Pin = gpio::Pin<gpio::DynPinId, gpio::FunctionPio1, gpio::PullDown>;
@@ -1,13 +1,21 @@
 use usbd_hid::descriptor::{generator_prelude::*, SerializedDescriptor};
 
+use crate::CONFIG_REPORT_SIZE;
+
 
 /// Gamepad Report Descriptor Template.
+/// The host can drive the lamps through the output report, one bit per lamp declared as a generic
+/// indicator of the LED page (see [`GamepadReport::lamps`]).
+/// The knobs' virtual buttons follow the arcade buttons (see [`GamepadReport::knob_buttons`]).
 #[derive(Default)]
 #[gen_hid_descriptor(
 	(collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = GAMEPAD) = {
 		(usage_page = BUTTON, usage_min = 0x1, usage_max = 0x7) = {
 			#[packed_bits 7] #[item_settings data,variable,absolute] buttons=input;
 		};
+		(usage_page = BUTTON, usage_min = 0x8, usage_max = 0xB) = {
+			#[packed_bits 4] #[item_settings data,variable,absolute] knob_buttons=input;
+		};
 		(usage_page = GENERIC_DESKTOP,) = {
 			(usage = X,) = {
 				#[item_settings data,variable,absolute] x=input;
@@ -16,17 +24,193 @@ use usbd_hid::descriptor::{generator_prelude::*, SerializedDescriptor};
 				#[item_settings data,variable,absolute] y=input;
 			};
 		};
+		(usage_page = LEDS, usage = 0x4B,) = {
+			#[packed_bits 7] #[item_settings data,variable,absolute] lamps=output;
+		};
 	}
 )]
 pub struct GamepadReport {
 	pub buttons: u8,
+	/// The knobs' virtual buttons, see [`KnobButton`](crate::KnobButton).
+	pub knob_buttons: u8,
 	pub x: u8,
 	pub y: u8,
+	/// Only sent by the host, following the GPIO pin order. Never included in the input report.
+	pub lamps: u8,
 }
 
 impl GamepadReport {
 	/// Generates a new Gamepad report.
-	pub fn new(buttons: u8, x: u8, y: u8) -> Self {
+	pub fn new(buttons: u8, knob_buttons: u8, x: u8, y: u8) -> Self {
+		Self { buttons, knob_buttons, x, y, lamps: 0 }
+	}
+}
+
+
+/// High Resolution Gamepad Report Descriptor Template.
+/// Same as the [`GamepadReport`], with 16-bit axes so slow encoder movements aren't quantized away.
+#[derive(Default)]
+#[gen_hid_descriptor(
+	(collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = GAMEPAD) = {
+		(usage_page = BUTTON, usage_min = 0x1, usage_max = 0x7) = {
+			#[packed_bits 7] #[item_settings data,variable,absolute] buttons=input;
+		};
+		(usage_page = BUTTON, usage_min = 0x8, usage_max = 0xB) = {
+			#[packed_bits 4] #[item_settings data,variable,absolute] knob_buttons=input;
+		};
+		(usage_page = GENERIC_DESKTOP,) = {
+			(usage = X,) = {
+				#[item_settings data,variable,absolute] x=input;
+			};
+			(usage = Y,) = {
+				#[item_settings data,variable,absolute] y=input;
+			};
+		};
+		(usage_page = LEDS, usage = 0x4B,) = {
+			#[packed_bits 7] #[item_settings data,variable,absolute] lamps=output;
+		};
+	}
+)]
+pub struct GamepadWideReport {
+	pub buttons: u8,
+	/// The knobs' virtual buttons, see [`KnobButton`](crate::KnobButton).
+	pub knob_buttons: u8,
+	pub x: u16,
+	pub y: u16,
+	/// Only sent by the host, following the GPIO pin order. Never included in the input report.
+	pub lamps: u8,
+}
+
+impl GamepadWideReport {
+	/// Generates a new high resolution Gamepad report.
+	pub fn new(buttons: u8, knob_buttons: u8, x: u16, y: u16) -> Self {
+		Self { buttons, knob_buttons, x, y, lamps: 0 }
+	}
+}
+
+
+/// Relative Gamepad Report Descriptor Template.
+/// Same as the [`GamepadReport`], with axes reporting the encoders' movement since the last report
+/// instead of their position.
+#[derive(Default)]
+#[gen_hid_descriptor(
+	(collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = GAMEPAD) = {
+		(usage_page = BUTTON, usage_min = 0x1, usage_max = 0x7) = {
+			#[packed_bits 7] #[item_settings data,variable,absolute] buttons=input;
+		};
+		(usage_page = BUTTON, usage_min = 0x8, usage_max = 0xB) = {
+			#[packed_bits 4] #[item_settings data,variable,absolute] knob_buttons=input;
+		};
+		(usage_page = GENERIC_DESKTOP,) = {
+			(usage = X,) = {
+				#[item_settings data,variable,relative] x=input;
+			};
+			(usage = Y,) = {
+				#[item_settings data,variable,relative] y=input;
+			};
+		};
+		(usage_page = LEDS, usage = 0x4B,) = {
+			#[packed_bits 7] #[item_settings data,variable,absolute] lamps=output;
+		};
+	}
+)]
+pub struct GamepadRelativeReport {
+	pub buttons: u8,
+	/// The knobs' virtual buttons, see [`KnobButton`](crate::KnobButton).
+	pub knob_buttons: u8,
+	pub x: i8,
+	pub y: i8,
+	/// Only sent by the host, following the GPIO pin order. Never included in the input report.
+	pub lamps: u8,
+}
+
+impl GamepadRelativeReport {
+	/// Generates a new relative Gamepad report.
+	pub fn new(buttons: u8, knob_buttons: u8, x: i8, y: i8) -> Self {
+		Self { buttons, knob_buttons, x, y, lamps: 0 }
+	}
+}
+
+
+/// Configuration Report Descriptor Template.
+/// Vendor-defined feature report carrying the messages of the configuration protocol, see [`ConfigMessage`](crate::ConfigMessage).
+#[derive(Default)]
+#[gen_hid_descriptor(
+	(collection = APPLICATION, usage_page = VENDOR_DEFINED_START, usage = 0x01) = {
+		(usage = 0x01,) = {
+			#[item_settings data,variable,absolute] data=feature;
+		};
+	}
+)]
+pub struct ConfigReport {
+	/// The macro only takes a literal length, which must match [`CONFIG_REPORT_SIZE`].
+	pub data: [u8; 8],
+}
+
+// Fails the build if the descriptor drifts apart from the protocol.
+const _: () = assert!(core::mem::size_of::<ConfigReport>() == CONFIG_REPORT_SIZE);
+
+
+/// Mouse Report Descriptor Template.
+#[derive(Default)]
+#[gen_hid_descriptor(
+	(collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = MOUSE) = {
+		(collection = PHYSICAL, usage = POINTER) = {
+			(usage_page = BUTTON, usage_min = 0x1, usage_max = 0x7) = {
+				#[packed_bits 7] #[item_settings data,variable,absolute] buttons=input;
+			};
+			(usage_page = GENERIC_DESKTOP,) = {
+				(usage = X,) = {
+					#[item_settings data,variable,relative] x=input;
+				};
+				(usage = Y,) = {
+					#[item_settings data,variable,relative] y=input;
+				};
+			};
+		};
+	}
+)]
+pub struct MouseReport {
+	pub buttons: u8,
+	pub x: i8,
+	pub y: i8,
+}
+
+impl MouseReport {
+	/// Generates a new Mouse report.
+	pub fn new(buttons: u8, x: i8, y: i8) -> Self {
 		Self { buttons, x, y }
 	}
 }
+
+
+/// Keyboard Report Descriptor Template.
+#[derive(Default)]
+#[gen_hid_descriptor(
+	(collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = KEYBOARD) = {
+		(usage_page = KEYBOARD, usage_min = 0xE0, usage_max = 0xE7) = {
+			#[packed_bits 8] #[item_settings data,variable,absolute] modifier=input;
+		};
+		(usage_page = KEYBOARD, usage_min = 0x00, usage_max = 0xDD) = {
+			#[item_settings data,array,absolute] keycodes=input;
+		};
+	}
+)]
+pub struct KeyboardReport {
+	pub modifier: u8,
+	/// One key slot per button plus one per encoder, so no simultaneous inputs are dropped.
+	pub keycodes: [u8; 9],
+}
+
+impl KeyboardReport {
+	/// Marks the given key as pressed. Modifier keys (0xE0-0xE7) are reported in the modifier byte,
+	/// the rest take the first free key slot. Keys are ignored once every slot is taken.
+	pub fn press(&mut self, key: u8) {
+		if (0xE0..=0xE7).contains(&key) {
+			self.modifier |= 1 << (key - 0xE0);
+		}
+		else if let Some(slot) = self.keycodes.iter_mut().find(|slot| **slot == 0) {
+			*slot = key;
+		}
+	}
+}
@@ -0,0 +1,139 @@
+use rp_pico as bsp;
+
+use bsp::hal;
+use hal::fugit::MicrosDurationU64;
+
+use crate::ENC_COUNTS_PER_PULSE;
+
+
+/// The default time (in microseconds) a knob button stays held after the knob's last count.
+pub const KNOB_BUTTON_DEFAULT_HOLD_US: u32 = 20_000;
+/// The default amount of counts a knob has to travel in one direction to press its button,
+/// a single pulse of the encoder.
+pub const KNOB_BUTTON_DEFAULT_THRESHOLD: u8 = ENC_COUNTS_PER_PULSE as u8;
+
+
+// Each knob presses one of two virtual buttons depending on the direction it spins in,
+// reported after the arcade buttons in the following order:
+// [VOL-L left] -> [VOL-L right] -> [VOL-R left] -> [VOL-R right]
+
+
+/// Turns a knob's rotation into a pair of virtual buttons, one per direction.
+#[derive(Default)]
+pub struct KnobButton {
+	/// The counts traveled in the current direction, reset when the knob turns back or rests.
+	travel: i32,
+	/// The moment of the last count.
+	last_pulse: Option<hal::timer::Instant>,
+	/// The direction (`-1` or `1`) of the held button, `0` while released.
+	held: i32,
+}
+
+impl KnobButton {
+	/// Registers the knob's movement at the given moment.
+	///
+	/// A button is pressed once the knob travels at least `threshold` counts in its direction
+	/// without resting for longer than `hold`, and stays held until the knob rests for `hold`.
+	///
+	/// Returns the direction of the held button (`-1` or `1`), or `0` if both are released.
+	pub fn update(
+		&mut self,
+		delta: i32,
+		now: hal::timer::Instant,
+		threshold: u8,
+		hold: MicrosDurationU64,
+	) -> i32 {
+		let rested = self.last_pulse
+			.and_then(|last_pulse| now.checked_duration_since(last_pulse))
+			.is_none_or(|elapsed| elapsed > hold);
+
+		if rested {
+			self.travel = 0;
+			self.held = 0;
+		}
+
+		if delta != 0 {
+			// Turning back starts over from the new direction.
+			if self.travel.signum() == -delta.signum() {
+				self.travel = 0;
+				self.held = 0;
+			}
+
+			self.travel = self.travel.saturating_add(delta);
+			self.last_pulse = Some(now);
+
+			if self.travel.unsigned_abs() >= threshold.max(1) as u32 {
+				self.held = self.travel.signum();
+			}
+		}
+
+		self.held
+	}
+
+	/// Returns the direction of the held button (`-1` or `1`), or `0` if both are released.
+	pub fn direction(&self) -> i32 {
+		self.held
+	}
+
+	/// Returns the knob's virtual buttons: bit 0 for the left (counter-clockwise) button and
+	/// bit 1 for the right (clockwise) one.
+	pub fn buttons(&self) -> u8 {
+		match self.held {
+			-1 => 0b01,
+			1 => 0b10,
+			_ => 0,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::at;
+
+	const HOLD: MicrosDurationU64 = MicrosDurationU64::micros(1000);
+
+	#[test]
+	fn jitter_below_the_threshold_is_ignored() {
+		let mut knob = KnobButton::default();
+
+		assert_eq!(knob.update(1, at(0), 3, HOLD), 0);
+		assert_eq!(knob.update(-1, at(100), 3, HOLD), 0);
+		assert_eq!(knob.update(1, at(200), 3, HOLD), 0);
+		assert_eq!(knob.update(1, at(300), 3, HOLD), 0);
+		assert_eq!(knob.update(1, at(400), 3, HOLD), 1);
+		assert_eq!(knob.buttons(), 0b10);
+	}
+
+	#[test]
+	fn button_is_held_until_the_knob_rests() {
+		let mut knob = KnobButton::default();
+
+		assert_eq!(knob.update(-2, at(0), 2, HOLD), -1);
+		assert_eq!(knob.update(0, at(500), 2, HOLD), -1);
+		assert_eq!(knob.update(-1, at(900), 2, HOLD), -1);
+		assert_eq!(knob.update(0, at(1900), 2, HOLD), -1);
+		assert_eq!(knob.update(0, at(1901), 2, HOLD), 0);
+		assert_eq!(knob.buttons(), 0);
+	}
+
+	#[test]
+	fn slow_pulses_never_reach_the_threshold() {
+		let mut knob = KnobButton::default();
+
+		for i in 0..10 {
+			assert_eq!(knob.update(1, at(i * 1500), 2, HOLD), 0);
+		}
+	}
+
+	#[test]
+	fn turning_back_switches_buttons() {
+		let mut knob = KnobButton::default();
+
+		assert_eq!(knob.update(2, at(0), 2, HOLD), 1);
+		assert_eq!(knob.update(-1, at(100), 2, HOLD), 0);
+		assert_eq!(knob.update(-1, at(200), 2, HOLD), -1);
+		assert_eq!(knob.buttons(), 0b01);
+	}
+}
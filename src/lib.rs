@@ -1,29 +1,58 @@
 #![no_std]
 #![allow(static_mut_refs)]
 
+pub mod acceleration;
+pub mod config_class;
 pub mod controller;
 pub mod hid_desc;
+pub mod knob;
+pub mod lighting;
+pub mod menu;
+pub mod movement;
+pub mod rgb;
+pub mod storage;
 
+pub use crate::acceleration::*;
+pub use crate::config_class::*;
 pub use crate::controller::*;
 pub use crate::hid_desc::*;
+pub use crate::knob::*;
+pub use crate::lighting::*;
+pub use crate::menu::*;
+pub use crate::movement::*;
+pub use crate::rgb::*;
+pub use pico_sdvx_proto::*;
 
 use rp_pico as bsp;
 
 use bsp::hal;
+use hal::dma;
 use hal::pac;
 use hal::pio;
 
+use core::ptr::addr_of_mut;
+
+
+/// The latest count pushed by each encoder's state machine, kept up to date by the DMA.
+static mut ENCODER_COUNTS: [u32; ENC_GPIO_SIZE] = [0; ENC_GPIO_SIZE];
+
 
 /// Loads the provided encoder program into the specified PIO0 state machine.
 /// The user must manage and start the state machine independently.
+///
+/// Pin B must be the GPIO right after pin A, see [`encoder_pins_adjacent`].
+///
+/// The pins are sampled every `filter_us` microseconds (see [`encoder_clock_divisor`]),
+/// `0` sampling them as fast as possible.
 /// 
 /// Returns the configured state machine, the receiver and the transmiter in a tuple.
+#[allow(clippy::type_complexity)]
 pub fn load_encoder_program<SM: pio::StateMachineIndex>(
 	program: pio::InstalledProgram<pac::PIO0>,
 	sm: pio::UninitStateMachine<(pac::PIO0, SM)>,
 	pin_a: u8,
 	pin_b: u8,
-	debounce: bool,
+	filter_us: u32,
 ) -> (
 	pio::StateMachine<(pac::PIO0, SM), pio::Stopped>,
 	pio::Rx<(pac::PIO0, SM)>,
@@ -39,6 +68,8 @@ pub fn load_encoder_program<SM: pio::StateMachineIndex>(
 		.jmp_pin(pin_b)
 		.autopull(false)
 		.in_shift_direction(pio::ShiftDirection::Left)
+		// The TX FIFO is unused, joining it doubles the counts the RX FIFO can queue.
+		.buffers(pio::Buffers::OnlyRx)
 		.build(sm);
 
 	sm.set_pindirs([
@@ -46,35 +77,354 @@ pub fn load_encoder_program<SM: pio::StateMachineIndex>(
 		(pin_b, pio::PinDir::Input),
 	]);
 
-	if debounce {
-		sm.set_clock_divisor(5000.0);
-	}
+	let (int, frac) = encoder_clock_divisor(filter_us, SYS_CLOCK_HZ);
+	sm.clock_divisor_fixed_point(int, frac);
 
 	(sm, rx, tx)
 }
 
-// TODO: Figure out a way to use DMA to improve performance (if possible).
-/// Reads data from the encoder and updates the delta to report which direction
+/// Returns whether the encoder program can read the given pins, which it does in one go
+/// (`in pins, 2`) starting from pin A, so pin B must be the GPIO right after it.
+pub fn encoder_pins_adjacent(pin_a: u8, pin_b: u8) -> bool {
+	pin_a.checked_add(1) == Some(pin_b)
+}
+
+/// Converts the time (in microseconds) between two samples of the encoder pins to the clock
+/// divisor of the encoders' state machines, as its integer and 1/256th parts.
+///
+/// Each sample takes [`ENC_FILTER_LOOP_CYCLES`] cycles of the state machine, so bounces shorter
+/// than the filter are mostly skipped. The divisor is clamped to the range supported by the PIO,
+/// from full speed up to about [`ENC_MAX_FILTER_US`] at 125MHz.
+pub fn encoder_clock_divisor(filter_us: u32, sys_clock_hz: u32) -> (u16, u8) {
+	// Computed in 1/256ths of a cycle, matching the fractional part of the divisor.
+	let divisor = (filter_us as u64 * sys_clock_hz as u64).saturating_mul(256)
+		/ (1_000_000 * ENC_FILTER_LOOP_CYCLES as u64);
+	let divisor = divisor.clamp(1 << 8, (u16::MAX as u64) << 8 | 0xFF);
+
+	((divisor >> 8) as u16, divisor as u8)
+}
+
+/// Loads the WS2812 program into the specified PIO1 state machine, driving the given pin.
+/// The user must manage and start the state machine independently.
+///
+/// Returns the configured state machine and the transmitter in a tuple.
+#[allow(clippy::type_complexity)]
+pub fn load_ws2812_program<SM: pio::StateMachineIndex>(
+	program: pio::InstalledProgram<pac::PIO1>,
+	sm: pio::UninitStateMachine<(pac::PIO1, SM)>,
+	pin: u8,
+) -> (
+	pio::StateMachine<(pac::PIO1, SM), pio::Stopped>,
+	pio::Tx<(pac::PIO1, SM)>
+) {
+	// Pixels are pushed as 24 bits (GRB) aligned to the MSB, which are shifted out first.
+	// Each bit takes 10 cycles, so the state machine runs at 8MHz (125MHz / 15.625) for 800kHz.
+	let (mut sm, _, tx) = pio::PIOBuilder::from_installed_program(program)
+		.side_set_pin_base(pin)
+		.out_shift_direction(pio::ShiftDirection::Left)
+		.autopull(true)
+		.pull_threshold(24)
+		.buffers(pio::Buffers::OnlyTx)
+		.clock_divisor_fixed_point(15, 160)
+		.build(sm);
+
+	sm.set_pindirs([(pin, pio::PinDir::Output)]);
+
+	(sm, tx)
+}
+
+/// The latest count of an encoder, which the DMA keeps overwriting as the encoder's state machine
+/// pushes new counts, so the RX FIFO never fills up and stalls the state machine.
+#[derive(Clone, Copy)]
+pub struct EncoderCount(*mut u32);
+
+impl EncoderCount {
+	/// Returns the count of the encoder at the given index, following the GPIO pin order.
+	///
+	/// # Safety
+	///
+	/// Only one DMA transfer may write to each count.
+	pub unsafe fn new(index: usize) -> Self {
+		Self(unsafe { addr_of_mut!(ENCODER_COUNTS[index]) })
+	}
+
+	/// Returns the last count written by the DMA.
+	pub fn read(&self) -> u32 {
+		unsafe { self.0.read_volatile() }
+	}
+}
+
+// Safety: The count is a static, always written at the same address.
+unsafe impl dma::WriteTarget for EncoderCount {
+	type TransmittedWord = u32;
+
+	fn tx_treq() -> Option<u8> {
+		None
+	}
+
+	fn tx_address_count(&mut self) -> (u32, u32) {
+		(self.0 as u32, u32::MAX)
+	}
+
+	fn tx_increment(&self) -> bool {
+		false
+	}
+}
+
+impl dma::EndlessWriteTarget for EncoderCount {}
+
+/// A DMA transfer copying the counts pushed to an encoder's RX FIFO into its [`EncoderCount`].
+pub type EncoderTransfer<CH, SM> = dma::single_buffer::Transfer<
+	dma::Channel<CH>,
+	pio::Rx<(pac::PIO0, SM)>,
+	EncoderCount,
+>;
+
+/// Starts copying every count pushed to the RX FIFO into the given count, paced by the FIFO.
+pub fn start_encoder_transfer<CH: dma::ChannelIndex, SM: pio::StateMachineIndex>(
+	ch: dma::Channel<CH>,
+	rx: pio::Rx<(pac::PIO0, SM)>,
+	count: EncoderCount,
+) -> EncoderTransfer<CH, SM> {
+	dma::single_buffer::Config::new(ch, rx, count).start()
+}
+
+/// Restarts the transfer once it has run out of transfers (after `u32::MAX` counts).
+pub fn renew_encoder_transfer<CH: dma::ChannelIndex, SM: pio::StateMachineIndex>(
+	transfer: EncoderTransfer<CH, SM>,
+) -> EncoderTransfer<CH, SM> {
+	if !transfer.is_done() { return transfer; }
+
+	let (ch, rx, count) = transfer.wait();
+
+	start_encoder_transfer(ch, rx, count)
+}
+
+/// Reports which of the PIO0 state machines stalled on a full RX FIFO since the last call,
+/// as a bitmask following the state machines' index. The encoders miss pulses while stalled.
+///
+/// The stall flags are sticky, so a state machine stalling several times between two calls
+/// is only reported once.
+pub fn take_encoder_stalls() -> u8 {
+	let pio0 = unsafe { &*pac::PIO0::ptr() };
+	let stalls = pio0.fdebug().read().rxstall().bits();
+
+	// The flags are cleared by writing them back.
+	pio0.fdebug().write(|w| unsafe { w.rxstall().bits(stalls) });
+
+	stalls
+}
+
+/// Updates the delta from the latest count of the encoder to report which direction
 /// is the encoder spinning.
-pub fn parse_encoder<SM: pio::StateMachineIndex>(
-	rx: &mut pio::Rx<(pac::PIO0, SM)>,
+///
+/// The sensitivity (in percent, see [`ENC_SENSITIVITY_SCALE`]) sets how many times a full
+/// revolution sweeps the axis, e.g. at `200` half a revolution covers the whole axis.
+/// The gain (in percent, see [`ACCEL_GAIN_SCALE`]) further scales the movement of this update,
+/// see [`AccelerationCurve`]. A gain of `0` keeps the position while still updating the delta.
+pub fn parse_encoder(
+	value: u32,
 	state: &mut EncoderState,
 	pulse: i32,
+	sensitivity: u16,
+	gain: u16,
 	reverse: bool,
 ) -> u8 {
 	let direction = if reverse { -1 } else { 1 };
+	let cycle = pulse as i64 * ENC_SENSITIVITY_SCALE as i64;
 
 	// Find the delta between the previous value and the current value and update it.
-	if let Some(value) = rx.read() {
-		state.curr_value += (value as i32 - state.prev_value as i32) * direction;
+	state.delta = value.wrapping_sub(state.prev_value) as i32 * direction;
+
+	// The travel is computed on 64 bits, as the gain can overflow it on large deltas.
+	let travel = state.delta as i64 * sensitivity as i64 * gain as i64 / ACCEL_GAIN_SCALE as i64;
+
+	state.curr_value = (state.curr_value as i64 + travel).rem_euclid(cycle) as i32;
+	state.prev_value = value;
+
+	encoder_position(state.curr_value, pulse)
+}
+
+/// Maps the encoder's travel (in hundredths of a count, within `0..pulse * ENC_SENSITIVITY_SCALE`)
+/// to the 0-255 range of an axis.
+///
+/// Integer math is used since the Cortex-M0+ has no FPU, and produces the same output
+/// as dividing by the pulses with floats (see the `encoder_bench` example for the latency gain).
+pub fn encoder_position(travel: i32, pulse: i32) -> u8 {
+	// 256 / 100 is reduced to 64 / 25, so the travel fits in an i32 at every resolution.
+	(travel * 64 / (pulse * 25)) as u8
+}
 
-		while state.curr_value < 0 {
-			state.curr_value = pulse + state.curr_value;
+/// Maps the encoder's travel (see [`encoder_position`]) to the 0-65535 range of a 16-bit axis.
+pub fn encoder_position_wide(travel: i32, pulse: i32) -> u16 {
+	let cycle = pulse * 25;
+	let scaled = travel * 64;
+
+	// The division is split in two bytes, so the products still fit in an i32 at every resolution.
+	let high = scaled / cycle;
+	let low = (scaled % cycle) * 256 / cycle;
+
+	(high << 8 | low) as u16
+}
+
+/// Returns the moment the given time (in microseconds) after boot, as a fake clock for the tests.
+#[cfg(test)]
+pub(crate) fn at(micros: u64) -> hal::timer::Instant {
+	hal::timer::Instant::from_ticks(micros)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The resolutions of the encoders used across cabinets.
+	const PPRS: [u16; 4] = [24, 100, 360, 600];
+
+	/// The position of the given count at the default sensitivity.
+	fn position(count: i32, pulse: i32) -> u8 {
+		encoder_position(count * ENC_SENSITIVITY_SCALE, pulse)
+	}
+
+	/// The mapping that used to be done with floats.
+	fn float_position(count: i32, pulse: i32) -> u8 {
+		((count as f64 / pulse as f64) * (u8::MAX as f64 + 1.0)) as u8
+	}
+
+	#[test]
+	fn encoder_position_matches_the_float_mapping() {
+		for pulse in PPRS.map(|ppr| ppr as i32 * ENC_COUNTS_PER_PULSE) {
+			for count in 0..pulse {
+				assert_eq!(position(count, pulse), float_position(count, pulse), "count {count}/{pulse}");
+			}
 		}
+	}
+
+	#[test]
+	fn encoder_position_wide_matches_the_float_mapping() {
+		for pulse in PPRS.map(|ppr| ppr as i32 * ENC_COUNTS_PER_PULSE) {
+			for travel in (0..pulse * ENC_SENSITIVITY_SCALE).step_by(7) {
+				let float = (travel as f64 / (pulse * ENC_SENSITIVITY_SCALE) as f64 * 65536.0) as u16;
+
+				assert_eq!(encoder_position_wide(travel, pulse), float, "travel {travel}/{pulse}");
+				assert_eq!((encoder_position_wide(travel, pulse) >> 8) as u8, encoder_position(travel, pulse));
+			}
+		}
+	}
+
+	#[test]
+	fn encoder_wraps_around_after_a_revolution() {
+		for ppr in PPRS {
+			let pulse = ppr as i32 * ENC_COUNTS_PER_PULSE;
+			let mut state = EncoderState::default();
 
-		state.curr_value %= pulse;
-		state.prev_value = value;
+			assert_eq!(parse_encoder(pulse as u32 - 1, &mut state, pulse, 100, 100, false), position(pulse - 1, pulse));
+			assert_eq!(parse_encoder(pulse as u32, &mut state, pulse, 100, 100, false), 0, "{ppr} PPR");
+			assert_eq!(parse_encoder(pulse as u32 * 3 + 1, &mut state, pulse, 100, 100, false), position(1, pulse));
+			assert_eq!(state.delta, pulse * 2 + 1);
+		}
+	}
+
+	#[test]
+	fn encoder_wraps_around_backwards() {
+		for ppr in PPRS {
+			let pulse = ppr as i32 * ENC_COUNTS_PER_PULSE;
+			let mut state = EncoderState::default();
+
+			// The state machine's count wraps below zero as well.
+			assert_eq!(parse_encoder(u32::MAX, &mut state, pulse, 100, 100, false), position(pulse - 1, pulse));
+			assert_eq!(state.delta, -1, "{ppr} PPR");
+			assert_eq!(parse_encoder(u32::MAX - pulse as u32, &mut state, pulse, 100, 100, false), position(pulse - 1, pulse));
+			assert_eq!(parse_encoder(0, &mut state, pulse, 100, 100, false), 0);
+		}
 	}
 
-	((state.curr_value as f64 / pulse as f64) * (u8::MAX as f64 + 1.0)) as u8
+	#[test]
+	fn sensitivity_scales_the_axis_sweep() {
+		for ppr in PPRS {
+			let pulse = ppr as i32 * ENC_COUNTS_PER_PULSE;
+			let mut double = EncoderState::default();
+			let mut half = EncoderState::default();
+
+			assert_eq!(parse_encoder(pulse as u32 / 4, &mut double, pulse, 200, 100, false), 128, "{ppr} PPR");
+			assert_eq!(parse_encoder(pulse as u32 / 2, &mut double, pulse, 200, 100, false), 0);
+			assert_eq!(parse_encoder(pulse as u32, &mut half, pulse, 50, 100, false), 128);
+			assert_eq!(parse_encoder(pulse as u32 * 2, &mut half, pulse, 50, 100, false), 0);
+			assert_eq!(parse_encoder(pulse as u32 * 2 - 2, &mut half, pulse, 50, 100, false), position(pulse - 1, pulse));
+		}
+	}
+
+	#[test]
+	fn encoder_pins_must_be_adjacent() {
+		assert!(encoder_pins_adjacent(14, 15));
+		assert!(encoder_pins_adjacent(16, 17));
+		assert!(!encoder_pins_adjacent(15, 14));
+		assert!(!encoder_pins_adjacent(14, 16));
+		assert!(!encoder_pins_adjacent(14, 14));
+		assert!(!encoder_pins_adjacent(u8::MAX, 0));
+	}
+
+	#[test]
+	fn encoder_clock_divisor_matches_float_division() {
+		for filter_us in [0, 1, 7, 60, 240, 1000, ENC_MAX_FILTER_US] {
+			let (int, frac) = encoder_clock_divisor(filter_us, SYS_CLOCK_HZ);
+			let divisor = int as f64 + frac as f64 / 256.0;
+			let expected = (filter_us as f64 * SYS_CLOCK_HZ as f64 / 1e6 / ENC_FILTER_LOOP_CYCLES as f64).max(1.0);
+
+			assert!((0.0..1.0 / 256.0).contains(&(expected - divisor)), "{filter_us}µs: {divisor} vs {expected}");
+		}
+
+		// The divisor that used to be hardcoded when debouncing the encoders.
+		assert_eq!(encoder_clock_divisor(240, SYS_CLOCK_HZ), (5000, 0));
+	}
+
+	#[test]
+	fn encoder_clock_divisor_is_clamped() {
+		assert_eq!(encoder_clock_divisor(0, SYS_CLOCK_HZ), (1, 0));
+		assert_eq!(encoder_clock_divisor(u32::MAX, SYS_CLOCK_HZ), (u16::MAX, u8::MAX));
+		assert_eq!(encoder_clock_divisor(u32::MAX, u32::MAX), (u16::MAX, u8::MAX));
+		assert!(encoder_clock_divisor(ENC_MAX_FILTER_US, SYS_CLOCK_HZ).0 < u16::MAX);
+	}
+
+	#[test]
+	fn gain_scales_the_movement() {
+		for ppr in PPRS {
+			let pulse = ppr as i32 * ENC_COUNTS_PER_PULSE;
+			let mut fast = EncoderState::default();
+			let mut slow = EncoderState::default();
+
+			assert_eq!(parse_encoder(pulse as u32 / 4, &mut fast, pulse, 100, 200, false), 128, "{ppr} PPR");
+			assert_eq!(parse_encoder(pulse as u32 / 2, &mut fast, pulse, 100, 200, true), 0);
+			assert_eq!(parse_encoder(pulse as u32, &mut slow, pulse, 100, 50, false), 128);
+			assert_eq!(fast.delta, -(pulse / 4));
+
+			// Without gain the position is kept, but the movement is still registered.
+			assert_eq!(parse_encoder(pulse as u32, &mut fast, pulse, 100, 0, false), 0);
+			assert_eq!(fast.delta, pulse / 2);
+		}
+	}
+
+	#[test]
+	fn encoder_position_fits_at_the_highest_settings() {
+		let pulse = u16::MAX as i32 * ENC_COUNTS_PER_PULSE;
+		let mut state = EncoderState::default();
+
+		assert_eq!(parse_encoder(pulse as u32 - 1, &mut state, pulse, ENC_MAX_SENSITIVITY, 100, false), u8::MAX);
+		assert_eq!(parse_encoder(pulse as u32 * 2 - 1, &mut state, pulse, ENC_MAX_SENSITIVITY, ACCEL_MAX_GAIN, false), u8::MAX);
+		assert_eq!(encoder_position(pulse * ENC_SENSITIVITY_SCALE - 1, pulse), u8::MAX);
+		assert_eq!(encoder_position_wide(pulse * ENC_SENSITIVITY_SCALE - 1, pulse), u16::MAX);
+	}
+
+	#[test]
+	fn reversed_encoder_wraps_around() {
+		for ppr in PPRS {
+			let pulse = ppr as i32 * ENC_COUNTS_PER_PULSE;
+			let mut state = EncoderState::default();
+
+			assert_eq!(parse_encoder(1, &mut state, pulse, 100, 100, true), position(pulse - 1, pulse), "{ppr} PPR");
+			assert_eq!(parse_encoder(pulse as u32 + 1, &mut state, pulse, 100, 100, true), position(pulse - 1, pulse));
+			assert_eq!(parse_encoder(0, &mut state, pulse, 100, 100, true), 0);
+		}
+	}
 }
+
@@ -0,0 +1,377 @@
+use rp_pico as bsp;
+
+use bsp::hal;
+use hal::fugit::MicrosDurationU64;
+
+use crate::ENC_GPIO_SIZE;
+
+
+/// The default brightness of the lamps.
+pub const LAMP_DEFAULT_BRIGHTNESS: u8 = u8::MAX;
+/// The default time (in microseconds) the lamps take to fade out once turned off.
+pub const LAMP_DEFAULT_FADE_DURATION_US: u64 = 100_000;
+/// The value at which the lamps' PWM counters wrap, giving them 12 bits of resolution.
+pub const LAMP_PWM_TOP: u16 = 4095;
+/// The clock divider of the lamps' PWM slices, running them at ~1kHz with a 125MHz system clock.
+pub const LAMP_PWM_DIVIDER: u8 = 30;
+/// The default time (in seconds) without any input before the idle animation starts.
+pub const IDLE_DEFAULT_TIMEOUT_S: u32 = 30;
+/// The time (in microseconds) each lamp stays lit in the chase and knight-rider animations.
+pub const IDLE_STEP_DURATION_US: u64 = 150_000;
+/// The period (in microseconds) of the pulse animation.
+pub const IDLE_PULSE_PERIOD_US: u64 = 2_000_000;
+/// The duration (in microseconds) the knob lighting keeps reacting after an encoder stops spinning.
+pub const KNOB_HOLD_DURATION_US: u64 = 150_000;
+/// The period (in microseconds) at which the FX lamps pulse while their encoder spins.
+pub const KNOB_PULSE_PERIOD_US: u64 = 100_000;
+/// The time (in microseconds) each lamp stays lit while sweeping in the encoder's direction.
+pub const KNOB_SWEEP_STEP_US: u64 = 60_000;
+
+
+// The lamps are lit following the GPIO pin order, see the layout in the controller module:
+// [START] -> [BT-A] -> [BT-B] -> [BT-C] -> [BT-D] -> [FX-L] -> [FX-R]
+
+/// The lamps walked by the chase animation, going around the buttons clockwise.
+const CHASE_LAMPS: [u8; 6] = [1, 2, 3, 4, 6, 5];
+/// The lamps swept by the knight-rider animation, from left to right.
+const SWEEP_LAMPS: [u8; 4] = [1, 2, 3, 4];
+/// The lamps pulsed by each encoder: FX-L for VOL-L and FX-R for VOL-R.
+const KNOB_PULSE_LAMPS: [u8; ENC_GPIO_SIZE] = [5, 6];
+
+
+/// Returns the PWM slice wired to the given GPIO pin.
+pub fn pwm_slice(pin: u8) -> usize {
+	((pin >> 1) & 0x7) as usize
+}
+
+/// Converts a brightness into the PWM level of a lamp.
+/// The curve is quadratic so fades look linear to the eye.
+pub fn pwm_level(brightness: u8) -> u16 {
+	let brightness = brightness as u32;
+
+	(brightness * brightness * LAMP_PWM_TOP as u32 / (u8::MAX as u32 * u8::MAX as u32)) as u16
+}
+
+/// Returns the brightness of a lamp fading out from the given brightness, once the given time
+/// has elapsed since it was turned off.
+pub fn fade_out(brightness: u8, elapsed: MicrosDurationU64, duration: MicrosDurationU64) -> u8 {
+	let Some(remaining) = duration.checked_sub(elapsed) else { return 0; };
+
+	if duration.ticks() == 0 { return 0; }
+
+	(brightness as u64 * remaining.ticks() / duration.ticks()) as u8
+}
+
+
+/// A lighting pattern played by the lamps.
+pub trait Animation {
+	/// Returns the lamps to light (following the GPIO pin order) once the given time has elapsed
+	/// since the animation started.
+	fn lamps(&self, elapsed: MicrosDurationU64) -> u8;
+}
+
+
+/// Lights one lamp at a time, going around the buttons.
+pub struct Chase;
+
+impl Animation for Chase {
+	fn lamps(&self, elapsed: MicrosDurationU64) -> u8 {
+		let step = (elapsed.to_micros() / IDLE_STEP_DURATION_US) as usize;
+
+		1 << CHASE_LAMPS[step % CHASE_LAMPS.len()]
+	}
+}
+
+
+/// Turns every lamp on and off at once.
+pub struct Pulse;
+
+impl Animation for Pulse {
+	fn lamps(&self, elapsed: MicrosDurationU64) -> u8 {
+		if elapsed.to_micros() % IDLE_PULSE_PERIOD_US < IDLE_PULSE_PERIOD_US / 2 { 0x7F } else { 0 }
+	}
+}
+
+
+/// Sweeps a single lamp back and forth across the BT buttons.
+pub struct KnightRider;
+
+impl Animation for KnightRider {
+	fn lamps(&self, elapsed: MicrosDurationU64) -> u8 {
+		// The lamps at both ends are only lit once per sweep.
+		let period = SWEEP_LAMPS.len() * 2 - 2;
+		let step = (elapsed.to_micros() / IDLE_STEP_DURATION_US) as usize % period;
+		let position = if step < SWEEP_LAMPS.len() { step } else { period - step };
+
+		1 << SWEEP_LAMPS[position]
+	}
+}
+
+
+/// Determines what the lamps show.
+/// Default is [`LightingMode::HostDriven`].
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum LightingMode {
+	/// The lamps stay off.
+	Off,
+	/// The lamps light up while their button is pressed.
+	Reactive,
+	/// The lamps light up while their button is released.
+	Inverted,
+	/// The lamps stay on.
+	AlwaysOn,
+	/// The lamps follow the host's output reports, or react to the buttons if the host stops
+	/// sending them (see [`HOST_LIGHTS_TIMEOUT_US`](crate::HOST_LIGHTS_TIMEOUT_US)).
+	#[default] HostDriven,
+}
+
+impl LightingMode {
+	/// Every lighting mode, ordered by their serialized value.
+	pub const ALL: [LightingMode; 5] = [
+		LightingMode::Off,
+		LightingMode::Reactive,
+		LightingMode::Inverted,
+		LightingMode::AlwaysOn,
+		LightingMode::HostDriven,
+	];
+
+	/// Returns the lamps lit by this mode for the given pressed buttons and knob lamps
+	/// (following the GPIO pin order), leaving out the host's lamps and the idle animation.
+	pub fn lamps(&self, buttons: u8, knob_lamps: u8) -> u8 {
+		match self {
+			LightingMode::Off => 0,
+			LightingMode::Reactive | LightingMode::HostDriven => buttons | knob_lamps,
+			// The knob lamps are toggled, as most lamps are already lit.
+			LightingMode::Inverted => !buttons ^ knob_lamps,
+			LightingMode::AlwaysOn => u8::MAX | knob_lamps,
+		}
+	}
+}
+
+
+/// Determines the animation played by the lamps while the controller is idle.
+/// Default is [`IdleAnimation::None`].
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum IdleAnimation {
+	/// The lamps stay off while idle.
+	#[default] None,
+	/// See [`Chase`].
+	Chase,
+	/// See [`Pulse`].
+	Pulse,
+	/// See [`KnightRider`].
+	KnightRider,
+}
+
+impl IdleAnimation {
+	/// Every idle animation, ordered by their serialized value.
+	pub const ALL: [IdleAnimation; 4] = [
+		IdleAnimation::None,
+		IdleAnimation::Chase,
+		IdleAnimation::Pulse,
+		IdleAnimation::KnightRider,
+	];
+
+	/// Returns the animation to play, if any.
+	pub fn animation(&self) -> Option<&'static dyn Animation> {
+		match self {
+			IdleAnimation::None => None,
+			IdleAnimation::Chase => Some(&Chase),
+			IdleAnimation::Pulse => Some(&Pulse),
+			IdleAnimation::KnightRider => Some(&KnightRider),
+		}
+	}
+}
+
+
+/// Determines how the lamps react to the encoders spinning.
+/// The knob lamps light up along with the lighting mode's lamps, see [`LightingMode::lamps`].
+/// Default is [`KnobLighting::None`].
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum KnobLighting {
+	/// The lamps ignore the encoders.
+	#[default] None,
+	/// The FX lamp on the side of a spinning encoder pulses.
+	FxPulse,
+	/// A single lamp sweeps across the BT buttons in the direction of a spinning encoder.
+	Sweep,
+}
+
+impl KnobLighting {
+	/// Every knob lighting effect, ordered by their serialized value.
+	pub const ALL: [KnobLighting; 3] = [
+		KnobLighting::None,
+		KnobLighting::FxPulse,
+		KnobLighting::Sweep,
+	];
+
+	/// Returns the lamps to light (following the GPIO pin order) for the given encoder directions,
+	/// see [`EncoderState::direction`](crate::EncoderState::direction).
+	pub fn lamps(&self, directions: [i32; ENC_GPIO_SIZE], elapsed: MicrosDurationU64) -> u8 {
+		let mut lamps = 0;
+
+		for (i, direction) in directions.into_iter().enumerate() {
+			if direction == 0 { continue; }
+
+			match self {
+				KnobLighting::None => (),
+				KnobLighting::FxPulse => {
+					if elapsed.to_micros() % KNOB_PULSE_PERIOD_US < KNOB_PULSE_PERIOD_US / 2 {
+						lamps |= 1 << KNOB_PULSE_LAMPS[i];
+					}
+				}
+				KnobLighting::Sweep => {
+					let step = (elapsed.to_micros() / KNOB_SWEEP_STEP_US) as usize % SWEEP_LAMPS.len();
+					let position = if direction > 0 { step } else { SWEEP_LAMPS.len() - 1 - step };
+
+					lamps |= 1 << SWEEP_LAMPS[position];
+				}
+			}
+		}
+
+		lamps
+	}
+}
+
+
+/// Keeps track of the inputs to know when the controller becomes idle.
+#[derive(Default)]
+pub struct IdleTracker {
+	last_activity: Option<hal::timer::Instant>,
+}
+
+impl IdleTracker {
+	/// Registers whether there was any input at the given moment.
+	///
+	/// Returns how long the controller has been idle for (after the timeout), or `None` while active.
+	pub fn update(
+		&mut self,
+		now: hal::timer::Instant,
+		active: bool,
+		timeout: MicrosDurationU64,
+	) -> Option<MicrosDurationU64> {
+		if active || self.last_activity.is_none() {
+			self.last_activity = Some(now);
+		}
+
+		let inactive = now.checked_duration_since(self.last_activity?)?;
+
+		inactive.checked_sub(timeout)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::at;
+
+	fn frames(animation: &dyn Animation, count: u64, interval: u64) -> [u8; 8] {
+		let mut frames = [0; 8];
+
+		for (i, frame) in frames.iter_mut().take(count as usize).enumerate() {
+			*frame = animation.lamps(MicrosDurationU64::micros(i as u64 * interval));
+		}
+
+		frames
+	}
+
+	#[test]
+	fn fade_out_decreases_linearly_to_zero() {
+		let duration = MicrosDurationU64::micros(100_000);
+		let fade = |elapsed: u64| fade_out(200, MicrosDurationU64::micros(elapsed), duration);
+
+		assert_eq!(fade(0), 200);
+		assert_eq!(fade(25_000), 150);
+		assert_eq!(fade(50_000), 100);
+		assert_eq!(fade(100_000), 0);
+		assert_eq!(fade(250_000), 0);
+
+		let mut previous = fade(0);
+
+		for elapsed in (0..=100_000).step_by(1000) {
+			assert!(fade(elapsed) <= previous);
+			previous = fade(elapsed);
+		}
+	}
+
+	#[test]
+	fn fade_out_without_duration_turns_off() {
+		assert_eq!(fade_out(u8::MAX, MicrosDurationU64::micros(0), MicrosDurationU64::micros(0)), 0);
+	}
+
+	#[test]
+	fn pwm_level_covers_the_whole_range() {
+		assert_eq!(pwm_level(0), 0);
+		assert_eq!(pwm_level(u8::MAX), LAMP_PWM_TOP);
+		assert!((1..=u8::MAX).all(|brightness| pwm_level(brightness) >= pwm_level(brightness - 1)));
+	}
+
+	#[test]
+	fn chase_goes_around_the_buttons() {
+		let lamps = frames(&Chase, 8, IDLE_STEP_DURATION_US);
+
+		assert_eq!(lamps, [1 << 1, 1 << 2, 1 << 3, 1 << 4, 1 << 6, 1 << 5, 1 << 1, 1 << 2]);
+	}
+
+	#[test]
+	fn knight_rider_sweeps_back_and_forth() {
+		let lamps = frames(&KnightRider, 8, IDLE_STEP_DURATION_US);
+
+		assert_eq!(lamps, [1 << 1, 1 << 2, 1 << 3, 1 << 4, 1 << 3, 1 << 2, 1 << 1, 1 << 2]);
+	}
+
+	#[test]
+	fn pulse_toggles_every_lamp() {
+		let lamps = frames(&Pulse, 4, IDLE_PULSE_PERIOD_US / 2);
+
+		assert_eq!(lamps[..4], [0x7F, 0, 0x7F, 0]);
+	}
+
+	#[test]
+	fn knob_lighting_only_reacts_to_spinning_encoders() {
+		let still = MicrosDurationU64::micros(0);
+
+		assert_eq!(KnobLighting::FxPulse.lamps([0, 0], still), 0);
+		assert_eq!(KnobLighting::FxPulse.lamps([1, 0], still), 1 << 5);
+		assert_eq!(KnobLighting::FxPulse.lamps([0, -1], still), 1 << 6);
+		assert_eq!(KnobLighting::FxPulse.lamps([1, 0], MicrosDurationU64::micros(KNOB_PULSE_PERIOD_US / 2)), 0);
+		assert_eq!(KnobLighting::None.lamps([1, -1], still), 0);
+	}
+
+	#[test]
+	fn knob_lamps_keep_held_buttons_lit() {
+		let fx_l = 1 << 5;
+		let knob_lamps = KnobLighting::FxPulse.lamps([1, 0], MicrosDurationU64::micros(0));
+
+		assert_eq!(LightingMode::Reactive.lamps(fx_l, knob_lamps), fx_l);
+		assert_eq!(LightingMode::HostDriven.lamps(fx_l, knob_lamps), fx_l);
+		assert_eq!(LightingMode::Reactive.lamps(0, knob_lamps), fx_l);
+		assert_eq!(LightingMode::AlwaysOn.lamps(0, knob_lamps), u8::MAX);
+		assert_eq!(LightingMode::Inverted.lamps(0, knob_lamps), !fx_l);
+		assert_eq!(LightingMode::Off.lamps(fx_l, knob_lamps), 0);
+	}
+
+	#[test]
+	fn knob_sweep_follows_the_encoder_direction() {
+		let sweep = |direction: i32, step: u64| {
+			KnobLighting::Sweep.lamps([direction, 0], MicrosDurationU64::micros(step * KNOB_SWEEP_STEP_US))
+		};
+
+		assert_eq!([sweep(1, 0), sweep(1, 1), sweep(1, 3), sweep(1, 4)], [1 << 1, 1 << 2, 1 << 4, 1 << 1]);
+		assert_eq!([sweep(-1, 0), sweep(-1, 1), sweep(-1, 3)], [1 << 4, 1 << 3, 1 << 1]);
+	}
+
+	#[test]
+	fn idle_starts_after_the_timeout_and_stops_on_input() {
+		let timeout = MicrosDurationU64::micros(1000);
+		let mut tracker = IdleTracker::default();
+
+		assert_eq!(tracker.update(at(0), false, timeout), None);
+		assert_eq!(tracker.update(at(999), false, timeout), None);
+		assert_eq!(tracker.update(at(1500), false, timeout), Some(MicrosDurationU64::micros(500)));
+		assert_eq!(tracker.update(at(1600), true, timeout), None);
+		assert_eq!(tracker.update(at(2599), false, timeout), None);
+		assert_eq!(tracker.update(at(2600), false, timeout), Some(MicrosDurationU64::micros(0)));
+	}
+}
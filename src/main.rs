@@ -21,6 +21,7 @@ use bsp::hal;
 use hal::pac;
 
 use hal::Timer;
+use hal::dma::DMAExt;
 use hal::pio::PIOExt;
 
 // The macro for interrupt functions.
@@ -33,7 +34,7 @@ use usb_device::{class_prelude::*, prelude::*};
 
 // USB Human Interface Device (HID) Class support.
 use usbd_hid::descriptor::generator_prelude::*;
-use usbd_hid::hid_class::HIDClass;
+use usbd_hid::hid_class::{HIDClass, ReportType};
 
 
 /// The USB Device Driver (shared with the interrupt).
@@ -42,8 +43,14 @@ static mut USB_DEVICE: Option<UsbDevice<hal::usb::UsbBus>> = None;
 /// The USB Bus Driver (shared with the interrupt).
 static mut USB_BUS: Option<UsbBusAllocator<hal::usb::UsbBus>> = None;
 
-/// The USB Human Interface Device (HID) Driver (shared with the interrupt).
-static mut USB_HID: Option<HIDClass<hal::usb::UsbBus>> = None;
+/// The USB Human Interface Device (HID) Drivers, one per interface (shared with the interrupt).
+/// Only the interfaces used by the current HID mode are set.
+static mut USB_HID_GAMEPAD: Option<HIDClass<hal::usb::UsbBus>> = None;
+static mut USB_HID_KEYBOARD: Option<HIDClass<hal::usb::UsbBus>> = None;
+static mut USB_HID_MOUSE: Option<HIDClass<hal::usb::UsbBus>> = None;
+
+/// The USB configuration interface, used by the host to read and write the options (shared with the interrupt).
+static mut USB_CONFIG: Option<ConfigClass<hal::usb::UsbBus>> = None;
 
 
 #[entry]
@@ -78,6 +85,24 @@ fn main() -> ! {
 		&mut pac.RESETS,
 	);
 
+	// The lamps are driven by the PWM slices.
+	let pwm = hal::pwm::Slices::new(pac.PWM, &mut pac.RESETS).free();
+
+	SDVXController::init(pins, pwm, timer);
+
+	// Retrieves the controller instance.
+	let controller = SDVXController::get_mut().unwrap();
+
+	/* Check the SDVXControllerOptions struct for a full list of options. */
+	// controller.options()
+	// 	.with_hid_mode(HidMode::Composite)
+	// 	.with_debounce_mode(DebounceMode::Hold)
+	// 	.with_reverse_encoders(ReverseMode::Both);
+
+	let hid_mode = controller.hid_mode();
+	let axis_resolution = controller.axis_resolution();
+	let axis_mode = controller.axis_mode();
+
 	// Set up the USB driver.
 	let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
 		pac.USBCTRL_REGS,
@@ -90,8 +115,30 @@ fn main() -> ! {
 
 	let bus_ref = unsafe { USB_BUS.as_ref().unwrap() };
 
-	let usb_hid = HIDClass::new(bus_ref, GamepadReport::desc(), USB_HID_POLL_RATE_MS);
-	unsafe { USB_HID = Some(usb_hid) };
+	// Only the HID interfaces used by the HID mode are registered.
+	if hid_mode.uses(HidInterface::Gamepad) {
+		let desc = match (axis_mode, axis_resolution) {
+			(AxisMode::Relative, _) => GamepadRelativeReport::desc(),
+			(AxisMode::Absolute, AxisResolution::Standard) => GamepadReport::desc(),
+			(AxisMode::Absolute, AxisResolution::High) => GamepadWideReport::desc(),
+		};
+		let usb_hid = HIDClass::new(bus_ref, desc, USB_HID_POLL_RATE_MS);
+		unsafe { USB_HID_GAMEPAD = Some(usb_hid) };
+	}
+
+	if hid_mode.uses(HidInterface::Keyboard) {
+		let usb_hid = HIDClass::new_ep_in(bus_ref, KeyboardReport::desc(), USB_HID_POLL_RATE_MS);
+		unsafe { USB_HID_KEYBOARD = Some(usb_hid) };
+	}
+
+	if hid_mode.uses(HidInterface::Mouse) {
+		let usb_hid = HIDClass::new_ep_in(bus_ref, MouseReport::desc(), USB_HID_POLL_RATE_MS);
+		unsafe { USB_HID_MOUSE = Some(usb_hid) };
+	}
+
+	// The configuration interface is available regardless of the HID mode.
+	let usb_config = ConfigClass::new(bus_ref, USB_HID_POLL_RATE_MS);
+	unsafe { USB_CONFIG = Some(usb_config) };
 
 	// Set up the USB Device.
 	let usb_dev = UsbDeviceBuilder::new(bus_ref, UsbVidPid(0x00, 0x00))
@@ -116,44 +163,126 @@ fn main() -> ! {
 	// Parses and installs the encoder program into the PIO.
 	let program = pio_file!("./pio/encoders.pio");
 	let installed = pio0.install(&program.program).unwrap();
-	
-	SDVXController::init(pins, timer);
 
-	// Retrieves the controller instance.
-	let controller = SDVXController::get_mut().unwrap();
-	
-	/* Check the SDVXControllerOptions struct for a full list of options. */
-	// controller.options()
-	// 	.with_debounce_mode(DebounceMode::Hold)
-	// 	.with_reverse_encoders(ReverseMode::Both);
+	// Two DMA channels keep draining the encoders' RX FIFOs.
+	let dma = pac.DMA.split(&mut pac.RESETS);
+
+	controller.start(&installed, sm0, sm1, dma.ch0, dma.ch1);
 
-	controller.start(&installed, sm0, sm1);
+	// Retrieves the PIO1 and one of its state machines, then installs the RGB strip program.
+	let (mut pio1, sm0, ..) = pac.PIO1.split(&mut pac.RESETS);
+	let program = pio_file!("./pio/ws2812.pio");
+	let installed = pio1.install(&program.program).unwrap();
+
+	controller.start_rgb(&installed, sm0);
 
 	loop {
 		controller.update();
 
-		let report = controller.report_gamepad();
-
-		submit_report(report)
-			.ok()
-			.unwrap_or(0);
+		// Handles the pending request of the configuration protocol, if any.
+		let request = critical_section::with(|_| unsafe {
+			USB_CONFIG.as_mut().and_then(|config| config.pull_request())
+		});
+
+		if let Some(request) = request {
+			let response = controller.handle_config(&request);
+
+			critical_section::with(|_| unsafe {
+				USB_CONFIG.as_mut().map(|config| config.push_response(&response))
+			});
+		}
+
+		if let Some(lamps) = pull_lamps() {
+			controller.set_host_lamps(lamps);
+		}
+
+		if hid_mode.uses(HidInterface::Gamepad) {
+			match (axis_mode, axis_resolution) {
+				(AxisMode::Relative, _) => {
+					let report = controller.report_gamepad_relative();
+
+					// Movement is only discarded once the host has received it.
+					if submit_report(HidInterface::Gamepad, report).is_ok() {
+						controller.confirm_relative_report(&report);
+					}
+				}
+				(AxisMode::Absolute, AxisResolution::Standard) => {
+					submit_report(HidInterface::Gamepad, controller.report_gamepad())
+						.ok()
+						.unwrap_or(0);
+				}
+				(AxisMode::Absolute, AxisResolution::High) => {
+					submit_report(HidInterface::Gamepad, controller.report_gamepad_wide())
+						.ok()
+						.unwrap_or(0);
+				}
+			}
+		}
+
+		if hid_mode.uses(HidInterface::Keyboard) {
+			submit_report(HidInterface::Keyboard, controller.report_keyboard())
+				.ok()
+				.unwrap_or(0);
+		}
+
+		if hid_mode.uses(HidInterface::Mouse) {
+			let report = controller.report_mouse();
+
+			// Movement is only discarded once the host has received it.
+			if submit_report(HidInterface::Mouse, report).is_ok() {
+				controller.confirm_mouse_report(&report);
+			}
+		}
 	}
 }
 
 
-/// Submits a new report to the USB stack.
-fn submit_report(report: impl AsInputReport) -> Result<usize, UsbError> {
+/// Submits a new report to the given HID interface of the USB stack.
+fn submit_report(interface: HidInterface, report: impl AsInputReport) -> Result<usize, UsbError> {
 	critical_section::with(|_| unsafe {
-		USB_HID.as_mut().map(|hid| hid.push_input(&report))
+		let usb_hid = match interface {
+			HidInterface::Gamepad => USB_HID_GAMEPAD.as_mut(),
+			HidInterface::Keyboard => USB_HID_KEYBOARD.as_mut(),
+			HidInterface::Mouse => USB_HID_MOUSE.as_mut(),
+		};
+
+		usb_hid.map(|hid| hid.push_input(&report))
 	})
 	.unwrap()
 }
 
+/// Reads the lamps sent by the host through the gamepad's output report, if any.
+/// The report can either arrive through the OUT endpoint or a SET_REPORT request.
+fn pull_lamps() -> Option<u8> {
+	critical_section::with(|_| unsafe {
+		let usb_hid = USB_HID_GAMEPAD.as_mut()?;
+		let mut report = [0u8; 1];
+
+		if let Ok(1..) = usb_hid.pull_raw_output(&mut report) {
+			return Some(report[0]);
+		}
+
+		match usb_hid.pull_raw_report(&mut report) {
+			Ok(info) if info.report_type == ReportType::Output && info.len >= 1 => Some(report[0]),
+			_ => None,
+		}
+	})
+}
+
 /// This function is called whenever the USB hardware generates an interrupt request.
 #[interrupt]
 unsafe fn USBCTRL_IRQ() {
 	let usb_dev = USB_DEVICE.as_mut().unwrap();
-	let usb_hid = USB_HID.as_mut().unwrap();
-
-	usb_dev.poll(&mut [usb_hid]);
+	let usb_config = USB_CONFIG.as_mut().unwrap();
+
+	// Either a single HID interface is registered or all of them (composite mode).
+	match (USB_HID_GAMEPAD.as_mut(), USB_HID_KEYBOARD.as_mut(), USB_HID_MOUSE.as_mut()) {
+		(Some(gamepad), Some(keyboard), Some(mouse)) => {
+			usb_dev.poll(&mut [gamepad, keyboard, mouse, usb_config])
+		}
+		(Some(usb_hid), None, None) | (None, Some(usb_hid), None) | (None, None, Some(usb_hid)) => {
+			usb_dev.poll(&mut [usb_hid, usb_config])
+		}
+		_ => false,
+	};
 }
@@ -0,0 +1,248 @@
+use rp_pico as bsp;
+
+use bsp::hal;
+
+use crate::*;
+
+
+/// The duration (in microseconds) START has to be held for to enter the configuration menu.
+pub const MENU_HOLD_DURATION_US: u64 = 3_000_000;
+/// The amount of steps of the selected option's value in a full revolution of an encoder.
+pub const MENU_ENC_STEPS_PER_REVOLUTION: i32 = 24;
+/// The period (in microseconds) at which the selected option's lamp blinks.
+pub const MENU_BLINK_PERIOD_US: u64 = 500_000;
+/// The debounce durations (in microseconds) that can be selected from the menu.
+pub const MENU_DEBOUNCE_DURATION_PRESETS_US: [u64; 6] = [1000, 2000, 4000, 6000, 8000, 10000];
+/// The encoder filters (in microseconds) that can be selected from the menu.
+pub const MENU_ENCODER_FILTER_PRESETS_US: [u64; 6] = [0, 30, 60, 120, 240, 480];
+/// The options that can be changed from the menu, selected with BT-A, BT-B, BT-C and BT-D.
+pub const MENU_OPTIONS: [OptionField; 4] = [
+	OptionField::DebounceMode,		// BT-A
+	OptionField::DebounceDuration,	// BT-B
+	OptionField::ReverseEncoders,	// BT-C
+	OptionField::EncoderFilter,		// BT-D
+];
+
+
+// While the menu is open the lamps are used as follows:
+// [START] -> Always lit, pressing it again saves the options and closes the menu.
+// [BT-A..D] -> The lamp of the selected option blinks.
+// [BT-A..FX-R] -> The lamp at the position of the option's value is lit (BT-A is the first value).
+// Turning either encoder steps the selected option's value.
+
+
+/// On-device configuration menu, driven by the buttons and the encoders.
+pub struct ConfigMenu {
+	/// The option being changed, as an index of [`MENU_OPTIONS`].
+	selected: usize,
+	/// The buttons held during the previous update, so only new presses are handled.
+	last_buttons: u8,
+	/// Each encoder's travel that hasn't been turned into a step yet.
+	travel: [i32; ENC_GPIO_SIZE],
+	opened_at: hal::timer::Instant,
+}
+
+impl ConfigMenu {
+	/// Opens the menu. The buttons already held (usually START) are ignored until released.
+	pub fn new(now: hal::timer::Instant, buttons: u8) -> Self {
+		Self {
+			selected: 0,
+			last_buttons: buttons,
+			travel: [0; ENC_GPIO_SIZE],
+			opened_at: now,
+		}
+	}
+
+	/// Returns the option being changed.
+	pub fn selected(&self) -> OptionField {
+		MENU_OPTIONS[self.selected]
+	}
+
+	/// Handles the state of the buttons (following the GPIO pin order) and each encoder's movement.
+	///
+	/// Returns `true` once START is pressed, meaning the menu should be closed.
+	pub fn update(
+		&mut self,
+		options: &mut SDVXControllerOptions,
+		buttons: u8,
+		deltas: [i32; ENC_GPIO_SIZE],
+	) -> bool {
+		let pressed = buttons & !self.last_buttons;
+
+		self.last_buttons = buttons;
+
+		if pressed & 1 == 1 { return true; }
+
+		for i in 0..MENU_OPTIONS.len() {
+			if (pressed >> (i + 1)) & 1 == 1 {
+				self.selected = i;
+				self.travel = [0; ENC_GPIO_SIZE];
+			}
+		}
+
+		for (i, delta) in deltas.into_iter().enumerate() {
+			let step_pulses = (options.encoder_pulses(i) / MENU_ENC_STEPS_PER_REVOLUTION).max(1);
+
+			self.travel[i] += delta;
+
+			while self.travel[i].abs() >= step_pulses {
+				let step = self.travel[i].signum();
+
+				self.travel[i] -= step * step_pulses;
+				step_option(options, self.selected(), step);
+			}
+		}
+
+		false
+	}
+
+	/// Returns the lamps to light (following the GPIO pin order) to show the menu's state.
+	pub fn lamps(&self, options: &SDVXControllerOptions, now: hal::timer::Instant) -> u8 {
+		let elapsed = now.checked_duration_since(self.opened_at)
+			.map(|elapsed| elapsed.to_micros())
+			.unwrap_or(0);
+		let mut lamps = 1;
+
+		if let Some(position) = option_position(options, self.selected()) {
+			lamps |= 1 << (position + 1);
+		}
+
+		if elapsed % MENU_BLINK_PERIOD_US < MENU_BLINK_PERIOD_US / 2 {
+			lamps ^= 1 << (self.selected + 1);
+		}
+
+		lamps
+	}
+}
+
+
+/// Returns the values selectable from the menu for options that are too wide to step through.
+fn option_presets(field: OptionField) -> Option<&'static [u64]> {
+	match field {
+		OptionField::DebounceDuration => Some(&MENU_DEBOUNCE_DURATION_PRESETS_US),
+		OptionField::EncoderFilter => Some(&MENU_ENCODER_FILTER_PRESETS_US),
+		_ => None,
+	}
+}
+
+/// Returns the position of the option's value among the values selectable from the menu, if any.
+fn option_position(options: &SDVXControllerOptions, field: OptionField) -> Option<usize> {
+	let value = options.field(field, 0)?;
+
+	match option_presets(field) {
+		Some(presets) => presets.iter().position(|preset| *preset == value as u64),
+		None => Some(value as usize),
+	}
+}
+
+/// Moves the option's value one step forward or backward, stopping at the first and last values.
+fn step_option(options: &mut SDVXControllerOptions, field: OptionField, step: i32) {
+	let Some(value) = options.field(field, 0) else { return; };
+
+	let value = match (option_presets(field), field.kind()) {
+		// Durations outside of the presets step to the closest preset in that direction.
+		(Some(presets), _) => {
+			let value = value as u64;
+			let preset = if step > 0 {
+				presets.iter().find(|preset| **preset > value)
+			}
+			else {
+				presets.iter().rev().find(|preset| **preset < value)
+			};
+
+			match preset {
+				Some(preset) => *preset as u32,
+				None => return,
+			}
+		}
+		(_, OptionKind::Bool) => (value as i32 + step).clamp(0, 1) as u32,
+		(_, OptionKind::Enum(names)) => (value as i32 + step).clamp(0, names.len() as i32 - 1) as u32,
+		(_, OptionKind::Integer { min, max }) => (value as i64 + step as i64).clamp(min as i64, max as i64) as u32,
+	};
+
+	options.set_field(field, 0, value);
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::at;
+
+	const START: u8 = 1 << 0;
+	const BT_B: u8 = 1 << 2;
+	const BT_C: u8 = 1 << 3;
+	const BT_D: u8 = 1 << 4;
+	/// The pulses of a step at the default resolution.
+	const STEP: i32 = ENC_DEFAULT_PPR as i32 * ENC_COUNTS_PER_PULSE / MENU_ENC_STEPS_PER_REVOLUTION;
+
+	#[test]
+	fn start_closes_the_menu_once_pressed_again() {
+		let mut options = SDVXControllerOptions::default();
+		let mut menu = ConfigMenu::new(at(0), START);
+
+		assert!(!menu.update(&mut options, START, [0, 0]));
+		assert!(!menu.update(&mut options, 0, [0, 0]));
+		assert!(menu.update(&mut options, START, [0, 0]));
+	}
+
+	#[test]
+	fn encoders_step_and_clamp_the_selected_option() {
+		let mut options = SDVXControllerOptions::default();
+		let mut menu = ConfigMenu::new(at(0), 0);
+
+		menu.update(&mut options, BT_C, [0, 0]);
+		menu.update(&mut options, 0, [STEP - 1, 0]);
+		assert!(options.reverse_encoders() == ReverseMode::None);
+
+		menu.update(&mut options, 0, [1, 0]);
+		assert!(options.reverse_encoders() == ReverseMode::Both);
+
+		menu.update(&mut options, 0, [STEP * 10, 0]);
+		assert!(options.reverse_encoders() == ReverseMode::Right);
+
+		menu.update(&mut options, 0, [-STEP * 10, 0]);
+		assert!(options.reverse_encoders() == ReverseMode::None);
+	}
+
+	#[test]
+	fn durations_step_to_the_closest_preset() {
+		let mut options = SDVXControllerOptions::default();
+		let mut menu = ConfigMenu::new(at(0), 0);
+
+		options.set_field(OptionField::DebounceDuration, 0, 3000);
+		menu.update(&mut options, BT_B, [-STEP, 0]);
+		assert_eq!(options.field(OptionField::DebounceDuration, 0), Some(2000));
+
+		options.set_field(OptionField::DebounceDuration, 0, 3000);
+		menu.update(&mut options, 0, [0, STEP]);
+		assert_eq!(options.field(OptionField::DebounceDuration, 0), Some(4000));
+	}
+
+	#[test]
+	fn encoder_filter_steps_through_its_presets() {
+		let mut options = SDVXControllerOptions::default();
+		let mut menu = ConfigMenu::new(at(0), 0);
+
+		menu.update(&mut options, BT_D, [STEP * 4, 0]);
+		assert_eq!(options.encoder_filter().to_micros(), 240);
+
+		menu.update(&mut options, 0, [STEP * 10, 0]);
+		assert_eq!(options.encoder_filter().to_micros(), 480);
+
+		menu.update(&mut options, 0, [-STEP * 10, 0]);
+		assert_eq!(options.encoder_filter().to_micros(), 0);
+	}
+
+	#[test]
+	fn lamps_show_the_value_and_blink_the_selection() {
+		let mut options = SDVXControllerOptions::default();
+		let mut menu = ConfigMenu::new(at(0), 0);
+
+		menu.update(&mut options, BT_B, [0, 0]);
+
+		// The default duration is the third preset, shown on BT-C.
+		assert_eq!(menu.lamps(&options, at(0)), START | BT_B | BT_C);
+		assert_eq!(menu.lamps(&options, at(MENU_BLINK_PERIOD_US / 2)), START | BT_C);
+	}
+}
@@ -0,0 +1,94 @@
+use crate::ACCEL_GAIN_SCALE;
+
+
+/// Accumulates an encoder's movement until it is reported to the host as a relative value,
+/// keeping what didn't fit in a report for the next one.
+#[derive(Default)]
+pub struct PendingMovement {
+	/// The whole counts that haven't been reported yet.
+	counts: i32,
+	/// The movement below a count left over by the acceleration gain, in hundredths.
+	remainder: i32,
+}
+
+impl PendingMovement {
+	/// Adds the movement registered by an update, multiplied by `scale` and by the given gain
+	/// (in percent, see [`ACCEL_GAIN_SCALE`]).
+	pub fn add(&mut self, delta: i32, scale: i32, gain: u16) {
+		// The part of the movement below a count is carried over, so slow turns
+		// with a gain under 100% aren't lost.
+		let movement = delta * scale * gain as i32 + self.remainder;
+		let gain_scale = ACCEL_GAIN_SCALE as i32;
+
+		self.counts = self.counts.saturating_add(movement / gain_scale);
+		self.remainder = movement % gain_scale;
+	}
+
+	/// Returns the movement to send in the next report, clamped to what fits in a relative axis.
+	pub fn report(&self) -> i8 {
+		self.counts.clamp(-(i8::MAX as i32), i8::MAX as i32) as i8
+	}
+
+	/// Removes the movement of a report that was successfully sent to the host.
+	pub fn confirm(&mut self, sent: i8) {
+		self.counts -= sent as i32;
+	}
+
+	/// Returns the whole counts that haven't been reported yet.
+	pub fn counts(&self) -> i32 {
+		self.counts
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn movement_is_removed_once_confirmed() {
+		let mut movement = PendingMovement::default();
+
+		movement.add(3, 1, 100);
+		movement.add(-1, 1, 100);
+		assert_eq!(movement.report(), 2);
+
+		// A report that wasn't sent leaves the movement pending.
+		assert_eq!(movement.report(), 2);
+
+		movement.confirm(movement.report());
+		assert_eq!(movement.report(), 0);
+	}
+
+	#[test]
+	fn movement_beyond_a_report_is_kept() {
+		let mut movement = PendingMovement::default();
+
+		movement.add(100, 3, 100);
+		assert_eq!(movement.report(), i8::MAX);
+
+		movement.confirm(movement.report());
+		assert_eq!(movement.counts(), 300 - i8::MAX as i32);
+
+		movement.add(-200, 3, 100);
+		assert_eq!(movement.report(), -i8::MAX);
+	}
+
+	#[test]
+	fn gain_is_applied_without_losing_slow_movement() {
+		let mut movement = PendingMovement::default();
+
+		movement.add(4, 1, 250);
+		assert_eq!(movement.counts(), 10);
+
+		for _ in 0..4 {
+			movement.add(1, 1, 50);
+		}
+
+		assert_eq!(movement.counts(), 12);
+
+		movement.add(-1, 1, 50);
+		movement.add(-1, 1, 50);
+		assert_eq!(movement.counts(), 11);
+	}
+}
@@ -0,0 +1,223 @@
+use rp_pico as bsp;
+
+use bsp::hal;
+use hal::fugit::MicrosDurationU64;
+use hal::pio;
+
+use crate::*;
+
+
+/// The amount of LEDs lit by each encoder. The strip is split in one section per encoder,
+/// following the GPIO pin order (VOL-L first).
+pub const RGB_LEDS_PER_SIDE: usize = 12;
+/// The amount of LEDs on the strip.
+pub const RGB_LED_COUNT: usize = RGB_LEDS_PER_SIDE * ENC_GPIO_SIZE;
+/// The time (in microseconds) between frames sent to the strip (~60 FPS).
+pub const RGB_FRAME_DURATION_US: u64 = 16_667;
+/// The default color (0xRRGGBB) of each section: SDVX's blue for VOL-L and pink for VOL-R.
+pub const RGB_DEFAULT_COLORS: [u32; ENC_GPIO_SIZE] = [0x0050FF, 0xFF2080];
+/// The brightness of a section while its encoder is still.
+pub const RGB_BASE_BRIGHTNESS: u8 = 48;
+/// The brightness of a section while one of its buttons is pressed.
+pub const RGB_PRESSED_BRIGHTNESS: u8 = 144;
+/// The buttons lighting up each section, following the GPIO pin order.
+/// START, BT-A, BT-B and FX-L light up VOL-L, while START, BT-C, BT-D and FX-R light up VOL-R.
+pub const RGB_SECTION_BUTTONS: [u8; ENC_GPIO_SIZE] = [0b0100111, 0b1011001];
+/// The duration (in microseconds) a section keeps reacting after its encoder stops spinning.
+pub const RGB_KNOB_HOLD_DURATION_US: u64 = 150_000;
+/// The time (in microseconds) the comet takes to move by one LED while an encoder spins.
+pub const RGB_COMET_STEP_US: u64 = 40_000;
+/// The amount of LEDs lit by the comet, including its head.
+pub const RGB_COMET_LENGTH: usize = 4;
+
+
+/// A color with 8 bits per channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rgb {
+	pub r: u8,
+	pub g: u8,
+	pub b: u8,
+}
+
+impl Rgb {
+	/// Unpacks a color in the 0xRRGGBB format.
+	pub fn from_u32(color: u32) -> Self {
+		Self { r: (color >> 16) as u8, g: (color >> 8) as u8, b: color as u8 }
+	}
+
+	/// Returns the color at the given brightness.
+	pub fn scale(&self, brightness: u8) -> Self {
+		let scale = |channel: u8| (channel as u16 * brightness as u16 / u8::MAX as u16) as u8;
+
+		Self { r: scale(self.r), g: scale(self.g), b: scale(self.b) }
+	}
+
+	/// Packs the color in the format expected by the WS2812 program: GRB in the top 24 bits.
+	pub fn to_ws2812(&self) -> u32 {
+		(self.g as u32) << 24 | (self.r as u32) << 16 | (self.b as u32) << 8
+	}
+}
+
+
+/// Determines how the RGB strip reacts to the controller.
+/// Default is [`RgbEffect::Reactive`].
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum RgbEffect {
+	/// The strip stays off.
+	Off,
+	/// Each section stays lit with its color.
+	Static,
+	/// Each section glows with its color, brightens while its buttons are pressed and runs a comet
+	/// in the direction its encoder spins.
+	#[default] Reactive,
+}
+
+impl RgbEffect {
+	/// Every RGB effect, ordered by their serialized value.
+	pub const ALL: [RgbEffect; 3] = [RgbEffect::Off, RgbEffect::Static, RgbEffect::Reactive];
+}
+
+
+/// Draws a frame of the given effect.
+///
+/// The directions are the ones of each encoder, see [`EncoderState::direction`].
+pub fn render_rgb(
+	effect: RgbEffect,
+	colors: [Rgb; ENC_GPIO_SIZE],
+	directions: [i32; ENC_GPIO_SIZE],
+	buttons: u8,
+	elapsed: MicrosDurationU64,
+	frame: &mut [Rgb; RGB_LED_COUNT],
+) {
+	let step = (elapsed.to_micros() / RGB_COMET_STEP_US) as usize % RGB_LEDS_PER_SIDE;
+
+	for (i, section) in frame.chunks_exact_mut(RGB_LEDS_PER_SIDE).enumerate() {
+		let base = match effect {
+			RgbEffect::Off => 0,
+			RgbEffect::Static => u8::MAX,
+			RgbEffect::Reactive if buttons & RGB_SECTION_BUTTONS[i] != 0 => RGB_PRESSED_BRIGHTNESS,
+			RgbEffect::Reactive => RGB_BASE_BRIGHTNESS,
+		};
+
+		for (position, led) in section.iter_mut().enumerate() {
+			let comet = match (effect, directions[i]) {
+				(RgbEffect::Reactive, 0) | (RgbEffect::Off | RgbEffect::Static, _) => 0,
+				(RgbEffect::Reactive, direction) => {
+					// The distance from the head of the comet, going against its movement.
+					let distance = if direction > 0 {
+						(step + RGB_LEDS_PER_SIDE - position) % RGB_LEDS_PER_SIDE
+					}
+					else {
+						(position + step) % RGB_LEDS_PER_SIDE
+					};
+
+					match RGB_COMET_LENGTH.checked_sub(distance) {
+						Some(remaining) => (remaining * u8::MAX as usize / RGB_COMET_LENGTH) as u8,
+						None => 0,
+					}
+				}
+			};
+
+			*led = colors[i].scale(base.max(comet));
+		}
+	}
+}
+
+
+/// Sends frames to a WS2812 strip through the [`load_ws2812_program`] state machine.
+///
+/// Frames are fed to the FIFO a few pixels at a time, so sending one never blocks the controller.
+pub struct Ws2812 {
+	tx: pio::Tx<pio::PIO1SM0>,
+	pixels: [u32; RGB_LED_COUNT],
+	/// The next pixel to feed to the FIFO.
+	next: usize,
+}
+
+impl Ws2812 {
+	pub fn new(tx: pio::Tx<pio::PIO1SM0>) -> Self {
+		Self { tx, pixels: [0; RGB_LED_COUNT], next: RGB_LED_COUNT }
+	}
+
+	/// Reports whether the previous frame has been fed completely.
+	pub fn is_ready(&self) -> bool {
+		self.next >= RGB_LED_COUNT
+	}
+
+	/// Starts sending a new frame. See [`Ws2812::update`].
+	pub fn send(&mut self, frame: &[Rgb; RGB_LED_COUNT]) {
+		for (pixel, color) in self.pixels.iter_mut().zip(frame) {
+			*pixel = color.to_ws2812();
+		}
+
+		self.next = 0;
+	}
+
+	/// Feeds as many pixels of the current frame as the FIFO can hold.
+	pub fn update(&mut self) {
+		while self.next < RGB_LED_COUNT && self.tx.write(self.pixels[self.next]) {
+			self.next += 1;
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const BLUE: Rgb = Rgb { r: 0, g: 0, b: 255 };
+	const PINK: Rgb = Rgb { r: 255, g: 0, b: 255 };
+
+	fn render(effect: RgbEffect, directions: [i32; 2], buttons: u8, elapsed: u64) -> [Rgb; RGB_LED_COUNT] {
+		let mut frame = [Rgb::default(); RGB_LED_COUNT];
+
+		render_rgb(effect, [BLUE, PINK], directions, buttons, MicrosDurationU64::micros(elapsed), &mut frame);
+
+		frame
+	}
+
+	/// Returns the position of the brightest LED of a section.
+	fn head(section: &[Rgb]) -> usize {
+		(0..section.len()).max_by_key(|i| section[*i].b).unwrap()
+	}
+
+	#[test]
+	fn colors_are_packed_as_grb() {
+		let color = Rgb::from_u32(0x123456);
+
+		assert_eq!(color, Rgb { r: 0x12, g: 0x34, b: 0x56 });
+		assert_eq!(color.to_ws2812(), 0x3412_5600);
+	}
+
+	#[test]
+	fn static_and_off_ignore_the_inputs() {
+		let lit = render(RgbEffect::Static, [1, -1], 0x7F, 0);
+		let off = render(RgbEffect::Off, [1, -1], 0x7F, 0);
+
+		assert!(lit[..RGB_LEDS_PER_SIDE].iter().all(|led| *led == BLUE));
+		assert!(lit[RGB_LEDS_PER_SIDE..].iter().all(|led| *led == PINK));
+		assert!(off.iter().all(|led| *led == Rgb::default()));
+	}
+
+	#[test]
+	fn buttons_brighten_their_section() {
+		let frame = render(RgbEffect::Reactive, [0, 0], 1 << 5, 0);
+
+		assert_eq!(frame[0], BLUE.scale(RGB_PRESSED_BRIGHTNESS));
+		assert_eq!(frame[RGB_LEDS_PER_SIDE], PINK.scale(RGB_BASE_BRIGHTNESS));
+	}
+
+	#[test]
+	fn comet_follows_the_encoder_direction() {
+		let step = RGB_COMET_STEP_US;
+		let heads = |elapsed: u64| {
+			let frame = render(RgbEffect::Reactive, [1, -1], 0, elapsed);
+			(head(&frame[..RGB_LEDS_PER_SIDE]), head(&frame[RGB_LEDS_PER_SIDE..]))
+		};
+
+		assert_eq!(heads(0), (0, 0));
+		assert_eq!(heads(step), (1, RGB_LEDS_PER_SIDE - 1));
+		assert_eq!(heads(step * 2), (2, RGB_LEDS_PER_SIDE - 2));
+	}
+}
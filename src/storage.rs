@@ -0,0 +1,345 @@
+use rp_pico as bsp;
+
+use bsp::hal;
+use hal::rom_data;
+
+use crate::*;
+
+
+/// The offset (from the start of the flash) of the sector reserved for the options.
+/// Must match the `STORAGE` region in `memory.x`.
+pub const STORAGE_FLASH_OFFSET: u32 = 0x1FF000;
+/// The size of the sector reserved for the options, also the flash's erase size.
+pub const STORAGE_SIZE: usize = 4096;
+/// The flash's program size. Writes must be a multiple of it.
+pub const STORAGE_PAGE_SIZE: usize = 256;
+/// Identifies a valid options record ("SDVX" in ASCII).
+pub const STORAGE_MAGIC: u32 = 0x5844_5653;
+/// The version of the record format, bumped whenever it changes in an incompatible way.
+pub const STORAGE_VERSION: u16 = 1;
+/// The size of the record's header: magic (4 bytes), version (2 bytes) and payload length (2 bytes).
+pub const STORAGE_HEADER_SIZE: usize = 8;
+/// The size of each option entry: field (1 byte), index (1 byte) and value (4 bytes).
+pub const STORAGE_ENTRY_SIZE: usize = 6;
+/// The size of the checksum trailing the record.
+pub const STORAGE_CRC_SIZE: usize = 4;
+/// The field of the retired option enabling a fixed debounce of the encoders,
+/// migrated to [`OptionField::EncoderFilter`] when found in a record.
+pub const STORAGE_LEGACY_DEBOUNCE_ENCODERS: u8 = 0x01;
+/// The encoder filter (in microseconds) matching the retired debounce of the encoders.
+pub const STORAGE_LEGACY_ENCODER_FILTER_US: u32 = 240;
+
+/// The base address of the flash when it is memory mapped (XIP).
+const XIP_BASE: u32 = 0x1000_0000;
+/// The sector erase command of the flash.
+const SECTOR_ERASE_CMD: u8 = 0x20;
+
+
+// The options are stored as a record with the following layout (little endian):
+// [MAGIC] -> [VERSION] -> [PAYLOAD LENGTH] -> [ENTRY]... -> [CRC32]
+// Each entry holds a single value of an option: [FIELD] -> [INDEX] -> [VALUE]
+// Entries with an unknown field are skipped, so new options can be added without a version bump.
+
+
+/// The reasons why a stored record can be rejected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageError {
+	/// The record doesn't start with [`STORAGE_MAGIC`], usually because nothing was stored yet.
+	InvalidMagic,
+	/// The record was written with a different [`STORAGE_VERSION`].
+	UnsupportedVersion,
+	/// The payload length doesn't fit in the record or isn't made of whole entries.
+	InvalidLength,
+	/// The record's checksum doesn't match its contents.
+	InvalidChecksum,
+}
+
+
+/// Serializes the options into a record, returning its length in bytes.
+///
+/// The buffer must be able to hold every option, [`STORAGE_SIZE`] bytes is always enough.
+pub fn encode(options: &SDVXControllerOptions, buffer: &mut [u8]) -> usize {
+	let mut len = STORAGE_HEADER_SIZE;
+
+	for field in OptionField::ALL {
+		for index in 0..field.count() {
+			let value = options.field(field, index).unwrap_or(0);
+			let entry = &mut buffer[len..len + STORAGE_ENTRY_SIZE];
+
+			entry[0] = field as u8;
+			entry[1] = index as u8;
+			entry[2..].copy_from_slice(&value.to_le_bytes());
+			len += STORAGE_ENTRY_SIZE;
+		}
+	}
+
+	let payload_len = (len - STORAGE_HEADER_SIZE) as u16;
+
+	buffer[0..4].copy_from_slice(&STORAGE_MAGIC.to_le_bytes());
+	buffer[4..6].copy_from_slice(&STORAGE_VERSION.to_le_bytes());
+	buffer[6..8].copy_from_slice(&payload_len.to_le_bytes());
+
+	let crc = crc32(&buffer[..len]);
+
+	buffer[len..len + STORAGE_CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+
+	len + STORAGE_CRC_SIZE
+}
+
+/// Deserializes the options from a record, validating it first.
+///
+/// Options missing from the record keep their default value, as do entries holding invalid values.
+pub fn decode(record: &[u8]) -> Result<SDVXControllerOptions, StorageError> {
+	if record.len() < STORAGE_HEADER_SIZE + STORAGE_CRC_SIZE {
+		return Err(StorageError::InvalidLength);
+	}
+
+	let read_u16 = |at: usize| u16::from_le_bytes([record[at], record[at + 1]]);
+	let read_u32 = |at: usize| u32::from_le_bytes([record[at], record[at + 1], record[at + 2], record[at + 3]]);
+
+	if read_u32(0) != STORAGE_MAGIC {
+		return Err(StorageError::InvalidMagic);
+	}
+
+	if read_u16(4) != STORAGE_VERSION {
+		return Err(StorageError::UnsupportedVersion);
+	}
+
+	let payload_len = read_u16(6) as usize;
+	let len = STORAGE_HEADER_SIZE + payload_len;
+
+	if !payload_len.is_multiple_of(STORAGE_ENTRY_SIZE) || len + STORAGE_CRC_SIZE > record.len() {
+		return Err(StorageError::InvalidLength);
+	}
+
+	if read_u32(len) != crc32(&record[..len]) {
+		return Err(StorageError::InvalidChecksum);
+	}
+
+	let mut options = SDVXControllerOptions::default();
+
+	for entry in record[STORAGE_HEADER_SIZE..len].chunks_exact(STORAGE_ENTRY_SIZE) {
+		let value = u32::from_le_bytes([entry[2], entry[3], entry[4], entry[5]]);
+
+		match OptionField::from_id(entry[0]) {
+			Some(field) => {
+				options.set_field(field, entry[1] as usize, value);
+			}
+			// Records written before the encoder filter keep filtering the encoders as they used to.
+			None if entry[0] == STORAGE_LEGACY_DEBOUNCE_ENCODERS => {
+				let filter = if value == 1 { STORAGE_LEGACY_ENCODER_FILTER_US } else { 0 };
+				options.set_field(OptionField::EncoderFilter, 0, filter);
+			}
+			None => (),
+		}
+	}
+
+	Ok(options)
+}
+
+/// Computes the CRC-32 (IEEE 802.3) checksum of the given data.
+pub fn crc32(data: &[u8]) -> u32 {
+	let mut crc = u32::MAX;
+
+	for byte in data {
+		crc ^= *byte as u32;
+
+		for _ in 0..8 {
+			let mask = (crc & 1).wrapping_neg();
+			crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+		}
+	}
+
+	!crc
+}
+
+
+/// Reads the options stored in flash.
+/// Falls back to the default options if the record is missing or corrupted.
+pub fn load() -> SDVXControllerOptions {
+	let record = unsafe {
+		core::slice::from_raw_parts((XIP_BASE + STORAGE_FLASH_OFFSET) as *const u8, STORAGE_SIZE)
+	};
+
+	decode(record).unwrap_or_default()
+}
+
+/// Writes the options to flash, replacing the previous record.
+///
+/// Note: Interrupts are disabled while the flash is being written, which takes a few milliseconds.
+pub fn save(options: &SDVXControllerOptions) {
+	let mut buffer = [0xFFu8; STORAGE_SIZE];
+	let len = encode(options, &mut buffer);
+
+	// Only whole pages can be programmed, the rest of the sector is left erased.
+	let len = len.div_ceil(STORAGE_PAGE_SIZE) * STORAGE_PAGE_SIZE;
+
+	let functions = FlashFunctions {
+		connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+		flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+		flash_range_erase: rom_data::flash_range_erase::ptr(),
+		flash_range_program: rom_data::flash_range_program::ptr(),
+		flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+	};
+
+	// The second stage bootloader restores the fast XIP configuration once the flash is written.
+	// It has to be copied to RAM, since the flash can't be read in the meantime.
+	let mut boot2 = [0u32; 64];
+
+	unsafe {
+		core::ptr::copy_nonoverlapping(XIP_BASE as *const u32, boot2.as_mut_ptr(), boot2.len());
+	}
+
+	critical_section::with(|_| unsafe {
+		write_flash(&functions, boot2.as_ptr(), buffer.as_ptr(), len);
+	});
+}
+
+
+/// The ROM functions needed to write the flash, looked up in advance.
+struct FlashFunctions {
+	connect_internal_flash: unsafe extern "C" fn(),
+	flash_exit_xip: unsafe extern "C" fn(),
+	flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+	flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+	flash_flush_cache: unsafe extern "C" fn(),
+}
+
+/// Erases the storage sector and programs the given data into it.
+///
+/// This function runs from RAM and must not call anything located in flash,
+/// since the flash can't be read while it is being written.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_flash(functions: &FlashFunctions, boot2: *const u32, data: *const u8, len: usize) {
+	core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+
+	(functions.connect_internal_flash)();
+	(functions.flash_exit_xip)();
+	(functions.flash_range_erase)(STORAGE_FLASH_OFFSET, STORAGE_SIZE, STORAGE_SIZE as u32, SECTOR_ERASE_CMD);
+	(functions.flash_range_program)(STORAGE_FLASH_OFFSET, data, len);
+	(functions.flash_flush_cache)();
+
+	// Jump into the copy of the second stage bootloader (thumb mode) to re-enable XIP.
+	let boot2: unsafe extern "C" fn() = core::mem::transmute((boot2 as *const u8).add(1));
+	boot2();
+
+	core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn custom_options() -> SDVXControllerOptions {
+		let mut options = SDVXControllerOptions::default();
+
+		options
+			.with_encoder_filter(240)
+			.with_debounce_duration(1500)
+			.with_debounce_mode(DebounceMode::Wait)
+			.with_reverse_encoders(ReverseMode::Right)
+			.with_hid_mode(HidMode::Composite)
+			.with_mouse_sensitivity(3, 7);
+
+		options
+	}
+
+	#[test]
+	fn crc32_matches_reference() {
+		assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+		assert_eq!(crc32(&[]), 0);
+	}
+
+	#[test]
+	fn round_trip() {
+		let options = custom_options();
+		let mut buffer = [0xFFu8; STORAGE_SIZE];
+		let len = encode(&options, &mut buffer);
+
+		assert!(len <= STORAGE_SIZE);
+		assert!(decode(&buffer[..len]) == Ok(options));
+		assert!(decode(&buffer) == Ok(options));
+	}
+
+	#[test]
+	fn erased_flash_is_rejected() {
+		let buffer = [0xFFu8; STORAGE_SIZE];
+
+		assert!(decode(&buffer) == Err(StorageError::InvalidMagic));
+		assert!(decode(&buffer).unwrap_or_default() == SDVXControllerOptions::default());
+	}
+
+	#[test]
+	fn corrupted_record_is_rejected() {
+		let mut buffer = [0xFFu8; STORAGE_SIZE];
+		let len = encode(&custom_options(), &mut buffer);
+
+		for at in STORAGE_HEADER_SIZE..len {
+			let mut corrupted = buffer;
+			corrupted[at] ^= 0x01;
+
+			assert!(decode(&corrupted) == Err(StorageError::InvalidChecksum));
+		}
+	}
+
+	#[test]
+	fn other_version_is_rejected() {
+		let mut buffer = [0xFFu8; STORAGE_SIZE];
+		encode(&custom_options(), &mut buffer);
+		buffer[4..6].copy_from_slice(&(STORAGE_VERSION + 1).to_le_bytes());
+
+		assert!(decode(&buffer) == Err(StorageError::UnsupportedVersion));
+	}
+
+	#[test]
+	fn truncated_record_is_rejected() {
+		let mut buffer = [0xFFu8; STORAGE_SIZE];
+		let len = encode(&custom_options(), &mut buffer);
+
+		assert!(decode(&buffer[..len - 1]) == Err(StorageError::InvalidLength));
+		assert!(decode(&buffer[..4]) == Err(StorageError::InvalidLength));
+	}
+
+	#[test]
+	fn unknown_and_invalid_entries_are_skipped() {
+		// An unknown field followed by an out of range debounce mode.
+		let record = default_record_with(&[[0xEE, 0, 1, 0, 0, 0], [OptionField::DebounceMode as u8, 0, 9, 0, 0, 0]]);
+
+		assert!(decode(&record) == Ok(SDVXControllerOptions::default()));
+	}
+
+	#[test]
+	fn legacy_encoder_debounce_is_migrated() {
+		let filter = |value: u8| {
+			let record = default_record_with(&[[STORAGE_LEGACY_DEBOUNCE_ENCODERS, 0, value, 0, 0, 0]]);
+			decode(&record).map(|options| options.encoder_filter().to_micros())
+		};
+
+		assert_eq!(filter(1), Ok(STORAGE_LEGACY_ENCODER_FILTER_US));
+		assert_eq!(filter(0), Ok(0));
+		assert_eq!(encoder_clock_divisor(STORAGE_LEGACY_ENCODER_FILTER_US, SYS_CLOCK_HZ), (5000, 0));
+	}
+
+	/// Builds a valid record of the default options, followed by the given entries.
+	fn default_record_with(entries: &[[u8; STORAGE_ENTRY_SIZE]]) -> [u8; STORAGE_SIZE] {
+		let mut buffer = [0xFFu8; STORAGE_SIZE];
+		let len = encode(&SDVXControllerOptions::default(), &mut buffer);
+		let mut record = [0xFFu8; STORAGE_SIZE];
+		let mut at = len - STORAGE_CRC_SIZE;
+
+		record[..at].copy_from_slice(&buffer[..at]);
+
+		for entry in entries {
+			record[at..at + STORAGE_ENTRY_SIZE].copy_from_slice(entry);
+			at += STORAGE_ENTRY_SIZE;
+		}
+
+		record[6..8].copy_from_slice(&((at - STORAGE_HEADER_SIZE) as u16).to_le_bytes());
+		let crc = crc32(&record[..at]);
+		record[at..at + STORAGE_CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+
+		record
+	}
+}